@@ -0,0 +1,802 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen};
+
+use crate::errors::*;
+use crate::root_cache::root_key;
+use crate::utils::{parse_quorum_inst, verify_merkle, verify_signatures, SET_QUORUM_METADATA};
+use crate::*;
+
+pub const BEACON_PUBLIC_KEY_LEN: usize = 64;
+pub const BEACON_ADDRESS_LEN: usize = 20;
+
+/// Beacon keys of one committee, stored raw to halve storage and skip hex encoding on verify.
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone, PartialEq)]
+pub enum Committee {
+    // uncompressed secp256k1 public keys as returned by ecrecover
+    PublicKeys(Vec<[u8; 64]>),
+    // last 20 bytes of keccak256(public key)
+    Addresses(Vec<[u8; 20]>),
+    // merkle root over member addresses, signers prove membership with `signer_paths`.
+    // saves storage staking, not gas: verification still ecrecovers every signature and
+    // each path adds keccak hashes, see `test_gas_by_committee_size`
+    MerkleRoot {
+        root: [u8; 32],
+        size: u64,
+    },
+}
+
+impl Committee {
+    /// parse hex keys, either all 64 bytes public keys or all 20 bytes addresses
+    pub fn from_hex(keys: &[String]) -> Self {
+        let keys: Vec<Vec<u8>> = keys.iter()
+            .map(|key| hex::decode(key).expect(INVALID_BEACON_KEY))
+            .collect();
+        if keys.iter().all(|key| key.len() == BEACON_PUBLIC_KEY_LEN) {
+            Committee::PublicKeys(keys.iter().map(|key| *array_ref![key, 0, BEACON_PUBLIC_KEY_LEN]).collect())
+        } else if keys.iter().all(|key| key.len() == BEACON_ADDRESS_LEN) {
+            Committee::Addresses(keys.iter().map(|key| *array_ref![key, 0, BEACON_ADDRESS_LEN]).collect())
+        } else {
+            panic!("{}", INVALID_BEACON_KEY)
+        }
+    }
+
+    /// member addresses, none once only the merkle root is kept
+    pub fn addresses(&self) -> Option<Vec<[u8; 20]>> {
+        match self {
+            Committee::PublicKeys(keys) => Some(keys.iter().map(to_address).collect()),
+            Committee::Addresses(addresses) => Some(addresses.clone()),
+            Committee::MerkleRoot { .. } => None,
+        }
+    }
+
+    /// same committee keeping only the merkle root of its addresses
+    pub fn to_merkle_root(&self) -> Self {
+        let addresses = match self.addresses() {
+            Some(addresses) => addresses,
+            None => return self.clone(),
+        };
+        Committee::MerkleRoot {
+            root: committee_root(&addresses),
+            size: addresses.len() as u64,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Committee::PublicKeys(keys) => keys.len(),
+            Committee::Addresses(addresses) => addresses.len(),
+            Committee::MerkleRoot { size, .. } => *size as usize,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// whether `public_key` recovered from a signature belongs to beacon at `index`,
+    /// `path` is only read for merkle root committees
+    pub fn is_signer(&self, index: usize, public_key: &[u8; 64], path: &[[u8; 32]]) -> bool {
+        match self {
+            Committee::PublicKeys(keys) => keys.get(index) == Some(public_key),
+            Committee::Addresses(addresses) => addresses.get(index) == Some(&to_address(public_key)),
+            Committee::MerkleRoot { root, size } => {
+                (index as u64) < *size
+                    && path.len() == tree_depth(*size)
+                    && member_root(&to_address(public_key), index, path) == *root
+            }
+        }
+    }
+
+    /// hex keys for clients, the root alone for merkle root committees
+    pub fn to_hex(&self) -> Vec<String> {
+        match self {
+            Committee::PublicKeys(keys) => keys.iter().map(hex::encode).collect(),
+            Committee::Addresses(addresses) => addresses.iter().map(hex::encode).collect(),
+            Committee::MerkleRoot { root, .. } => vec![hex::encode(root)],
+        }
+    }
+}
+
+/// derive beacon address from its public key
+pub fn to_address(public_key: &[u8; 64]) -> [u8; 20] {
+    let hash = env::keccak256_array(public_key);
+    *array_ref![hash, 12, BEACON_ADDRESS_LEN]
+}
+
+/// merkle root over keccak256 of each address, leaves padded with zero hashes to a power of two
+pub fn committee_root(addresses: &[[u8; 20]]) -> [u8; 32] {
+    let mut level: Vec<[u8; 32]> = addresses.iter().map(|address| env::keccak256_array(address)).collect();
+    level.resize(1 << tree_depth(addresses.len() as u64), [0; 32]);
+    while level.len() > 1 {
+        level = level.chunks(2).map(|pair| hash_pair(&pair[0], &pair[1])).collect();
+    }
+    level[0]
+}
+
+/// rebuild committee root from the address at `index` and its sibling hashes, leaf first
+fn member_root(address: &[u8; 20], index: usize, path: &[[u8; 32]]) -> [u8; 32] {
+    let mut node = env::keccak256_array(address);
+    for (level, sibling) in path.iter().enumerate() {
+        node = if (index >> level) & 1 == 0 {
+            hash_pair(&node, sibling)
+        } else {
+            hash_pair(sibling, &node)
+        };
+    }
+    node
+}
+
+fn tree_depth(size: u64) -> usize {
+    (64 - size.max(1).saturating_sub(1).leading_zeros()) as usize
+}
+
+fn hash_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut pair = left.to_vec();
+    pair.extend_from_slice(right);
+    env::keccak256_array(&pair)
+}
+
+/// Committees whose quorum an instruction needs.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum VerificationPolicy {
+    Beacon,
+    BeaconAndBridge,
+}
+
+/// Share of a committee that must sign an instruction.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Quorum {
+    // signers must be more than numerator / denominator of the committee
+    pub numerator: u64,
+    pub denominator: u64,
+    // and at least this many
+    pub min_signers: u64,
+    // beacon height of the instruction that set this quorum
+    pub height: u128,
+}
+
+impl Default for Quorum {
+    fn default() -> Self {
+        Self {
+            numerator: 2,
+            denominator: 3,
+            min_signers: 1,
+            height: 0,
+        }
+    }
+}
+
+impl Quorum {
+    pub fn is_reached(&self, signers: usize, committee_size: usize) -> bool {
+        signers as u128 * self.denominator as u128 > committee_size as u128 * self.numerator as u128
+            && signers as u64 >= self.min_signers
+    }
+}
+
+/// Beacon committee active from `height` until the next committee starts.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CommitteeView {
+    pub height: u128,
+    pub beacons: Vec<String>,
+}
+
+#[near_bindgen]
+impl Vault {
+    // getters
+
+    /// get committee history ordered by starting height
+    pub fn get_committees(&self, from_index: Option<u64>, limit: Option<u64>) -> Vec<CommitteeView> {
+        self.beacons
+            .iter()
+            .skip(from_index.unwrap_or_default() as usize)
+            .take(limit.unwrap_or(u64::MAX) as usize)
+            .map(|(height, committee)| CommitteeView { height, beacons: committee.to_hex() })
+            .collect()
+    }
+
+    /// get total number of stored committees
+    pub fn get_committees_len(&self) -> u64 {
+        self.beacons.len()
+    }
+
+    /// get current beacon committee and its starting height
+    pub fn get_latest_committee(&self) -> CommitteeView {
+        let height = self.beacons.max().expect(INVALID_BEACON_LIST);
+        CommitteeView {
+            height,
+            beacons: self.beacons.get(&height).unwrap().to_hex(),
+        }
+    }
+
+    /// get bridge committee list by height
+    pub fn get_bridge_committee(&self, height: u128) -> Vec<String> {
+        self.get_bridge_committee_at(height).to_hex()
+    }
+
+    /// get bridge committee history ordered by starting height
+    pub fn get_bridge_committees(&self, from_index: Option<u64>, limit: Option<u64>) -> Vec<CommitteeView> {
+        self.bridge_committees
+            .iter()
+            .skip(from_index.unwrap_or_default() as usize)
+            .take(limit.unwrap_or(u64::MAX) as usize)
+            .map(|(height, committee)| CommitteeView { height, beacons: committee.to_hex() })
+            .collect()
+    }
+
+    /// get share of a committee that must sign instructions
+    pub fn get_quorum(&self) -> Quorum {
+        self.quorum.clone()
+    }
+
+    /// get committees that must sign instructions
+    pub fn get_verification_policy(&self) -> VerificationPolicy {
+        self.verification_policy
+    }
+
+    /// get beacon heights a superseded committee is kept
+    pub fn get_committee_retention(&self) -> u128 {
+        self.committee_retention
+    }
+
+    // setters
+
+    /// set quorum
+    ///
+    /// verify instruction signed under the current quorum and update the quorum,
+    /// instructions older than the one that set the current quorum are rejected.
+    /// the attached deposit pays the roots cached by verifying it
+    #[payable]
+    pub fn set_quorum(
+        &mut self,
+        quorum_info: InteractRequest,
+        bridge_info: Option<InteractRequest>,
+    ) -> Quorum {
+        let storage_before = env::storage_usage();
+        // verify instruction
+        self.verify_committees(&quorum_info, &bridge_info);
+        assert!(quorum_info.height > self.quorum.height, "{}", QUORUM_HEIGHT_MISMATCH);
+
+        // parse instruction
+        let inst = hex::decode(&quorum_info.inst).unwrap_or_default();
+        let (numerator, denominator, min_signers) = parse_quorum_inst(&inst, &self.get_shard_ids(SET_QUORUM_METADATA));
+
+        // never drop below a simple majority
+        if denominator == 0 || numerator >= denominator || numerator as u128 * 2 < denominator as u128 || min_signers == 0 {
+            panic!("{}", INVALID_QUORUM);
+        }
+        self.quorum = Quorum {
+            numerator,
+            denominator,
+            min_signers,
+            height: quorum_info.height,
+        };
+        self.charge_storage(Subsystem::Committees, storage_before);
+
+        self.quorum.clone()
+    }
+
+    /// update committees that must sign instructions
+    pub fn set_verification_policy(&mut self, policy: VerificationPolicy) {
+        self.assert_admin();
+        if policy == VerificationPolicy::BeaconAndBridge {
+            assert!(!self.bridge_committees.is_empty(), "{}", BRIDGE_COMMITTEE_NOT_FOUND);
+        }
+        self.verification_policy = policy;
+    }
+
+    /// update beacon heights a superseded committee is kept
+    pub fn set_committee_retention(&mut self, retention: u128) {
+        self.assert_admin();
+        self.committee_retention = retention;
+    }
+
+    /// compact committee
+    ///
+    /// replace the beacon or bridge committee starting at `height` by the merkle root of its
+    /// addresses, signatures against it must then carry `signer_paths`.
+    /// members are logged as "committee root {beacon|bridge} {height} {root} {address,...}"
+    /// for relayers to build those paths
+    pub fn compact_committee(&mut self, height: u128, bridge: bool) {
+        self.assert_admin();
+        let committees = if bridge { &mut self.bridge_committees } else { &mut self.beacons };
+        let committee = committees.get(&height).expect(BEACON_HEIGHT_NOT_FOUND);
+        let addresses = match committee.addresses() {
+            Some(addresses) => addresses,
+            None => return,
+        };
+        let root = committee.to_merkle_root();
+        let storage_before = env::storage_usage();
+        committees.insert(&height, &root);
+        self.record_storage(Subsystem::Committees, storage_before);
+        env::log_str(format!(
+            "committee root {} {} {} {}",
+            if bridge { "bridge" } else { "beacon" },
+            height,
+            root.to_hex()[0],
+            addresses.iter().map(hex::encode).collect::<Vec<_>>().join(","),
+        ).as_str());
+    }
+
+    /// prune committees
+    ///
+    /// remove up to `limit` oldest beacon or bridge committees replaced more than `committee_retention`
+    /// heights before the latest one, proofs at those heights can no longer be verified
+    pub fn prune_committees(&mut self, limit: u64, bridge: bool) -> u64 {
+        self.assert_admin();
+        let storage_before = env::storage_usage();
+        let committees = if bridge { &mut self.bridge_committees } else { &mut self.beacons };
+        let latest = committees.max().expect(if bridge { BRIDGE_COMMITTEE_NOT_FOUND } else { INVALID_BEACON_LIST });
+        let cutoff = latest.saturating_sub(self.committee_retention);
+
+        let mut pruned = 0;
+        while pruned < limit {
+            let oldest = committees.min().unwrap();
+            match committees.higher(&oldest) {
+                Some(next) if next <= cutoff => {
+                    committees.remove(&oldest);
+                    pruned += 1;
+                }
+                _ => break,
+            }
+        }
+        self.record_storage(Subsystem::Committees, storage_before);
+        env::log_str(format!("pruned {} committees", pruned).as_str());
+
+        pruned
+    }
+}
+
+impl Vault {
+    /// bridge committee active at bridge height
+    pub(crate) fn get_bridge_committee_at(&self, height: u128) -> Committee {
+        self.find_bridge_committee(height).expect(BRIDGE_COMMITTEE_NOT_FOUND)
+    }
+
+    fn find_bridge_committee(&self, height: u128) -> Option<Committee> {
+        let get_height_key = self.bridge_committees.lower(&(height + 1))?;
+        self.bridge_committees.get(&get_height_key)
+    }
+
+    /// verify instruction against the beacon committee and, under `BeaconAndBridge`,
+    /// the bridge committee proof of the same instruction
+    pub(crate) fn verify_committees(&mut self, info: &InteractRequest, bridge_info: &Option<InteractRequest>) {
+        let verified = self.try_verify_committees(info, bridge_info).unwrap_or_else(|err| panic!("{}", err));
+        self.cache_roots(&verified);
+    }
+
+    /// `verify_committees` reporting the failure instead of panicking, returns the
+    /// block roots whose signatures were verified for the caller to cache once it succeeds
+    pub(crate) fn try_verify_committees(
+        &self,
+        info: &InteractRequest,
+        bridge_info: &Option<InteractRequest>,
+    ) -> Result<Vec<[u8; 32]>, &'static str> {
+        let bridge_info = match self.verification_policy {
+            VerificationPolicy::Beacon => None,
+            VerificationPolicy::BeaconAndBridge => {
+                let bridge_info = bridge_info.as_ref().ok_or(MISSING_BRIDGE_PROOF)?;
+                let inst = hex::decode(&info.inst).unwrap_or_default();
+                if hex::decode(&bridge_info.inst).unwrap_or_default() != inst {
+                    return Err(BRIDGE_INST_MISMATCH);
+                }
+                Some(bridge_info)
+            }
+        };
+        let mut verified = vec![];
+        let committee = self.find_committee(info.height).ok_or(BEACON_HEIGHT_NOT_FOUND)?;
+        verified.extend(self.verify_cached(info, committee, false)?);
+        if let Some(bridge_info) = bridge_info {
+            let committee = self.find_bridge_committee(bridge_info.height).ok_or(BRIDGE_COMMITTEE_NOT_FOUND)?;
+            verified.extend(self.verify_cached(bridge_info, committee, true)?);
+        }
+        Ok(verified)
+    }
+
+    /// remember block roots returned by `try_verify_committees`
+    pub(crate) fn cache_roots(&mut self, keys: &[[u8; 32]]) {
+        for key in keys {
+            self.verified_roots.insert(key);
+        }
+    }
+
+    /// verify signatures once per block root, then the instruction inclusion,
+    /// returns the root key when its signatures were checked by this call
+    fn verify_cached(&self, info: &InteractRequest, committee: Committee, bridge: bool) -> Result<Option<[u8; 32]>, &'static str> {
+        let key = root_key(info, bridge, &self.quorum);
+        let verified = if self.verified_roots.contains(&key) {
+            None
+        } else {
+            verify_signatures(info, &committee, &self.quorum)?;
+            Some(key)
+        };
+        verify_merkle(info)?;
+        Ok(verified)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use crate::utils::{verify_inst, DEFAULT_SHARD_ID};
+    use near_sdk::test_utils::{accounts, get_logs, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    fn setup() -> Vault {
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(0)).build());
+        let mut vault = Vault::new(vec!["00".repeat(64)], 10);
+        vault.beacons.insert(&100, &Committee::Addresses(vec![[1; 20]]));
+        vault.beacons.insert(&200, &Committee::Addresses(vec![[2; 20]]));
+        vault.beacons.insert(&300, &Committee::Addresses(vec![[3; 20]]));
+        vault
+    }
+
+    #[test]
+    fn test_committee_keys() {
+        let public_key = [7u8; 64];
+        let keys = Committee::from_hex(&[hex::encode(public_key)]);
+        assert!(keys.is_signer(0, &public_key, &[]));
+        assert!(!keys.is_signer(0, &[8u8; 64], &[]));
+        assert!(!keys.is_signer(1, &public_key, &[]));
+
+        let addresses = Committee::from_hex(&[hex::encode(to_address(&public_key))]);
+        assert!(addresses.is_signer(0, &public_key, &[]));
+        assert!(!addresses.is_signer(0, &[8u8; 64], &[]));
+        assert_eq!(addresses.to_hex(), vec![hex::encode(to_address(&public_key))]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Beacon keys must all be 64 bytes public keys or 20 bytes addresses")]
+    fn test_committee_invalid_key() {
+        Committee::from_hex(&["00".repeat(32)]);
+    }
+
+    fn addresses() -> Vec<[u8; 20]> {
+        test_utils::beacons().iter()
+            .map(|key| {
+                let key = hex::decode(key).unwrap();
+                to_address(array_ref![key, 0, 64])
+            })
+            .collect()
+    }
+
+    /// sibling hashes of the address at `index`, leaf first
+    fn committee_path(addresses: &[[u8; 20]], index: usize) -> Vec<[u8; 32]> {
+        let mut level: Vec<[u8; 32]> = addresses.iter().map(|address| env::keccak256_array(address)).collect();
+        level.resize(1 << tree_depth(addresses.len() as u64), [0; 32]);
+        let mut path = vec![];
+        let mut index = index;
+        while level.len() > 1 {
+            path.push(level[index ^ 1]);
+            level = level.chunks(2).map(|pair| hash_pair(&pair[0], &pair[1])).collect();
+            index /= 2;
+        }
+        path
+    }
+
+    fn request_with_paths() -> InteractRequest {
+        let mut request = test_utils::signed_request();
+        request.signer_paths = request.indexes.iter()
+            .map(|index| committee_path(&addresses(), *index as usize))
+            .collect();
+        request
+    }
+
+    #[test]
+    fn test_committee_merkle_root() {
+        testing_env!(VMContextBuilder::new().build());
+        let keys = Committee::from_hex(&test_utils::beacons());
+        let root = keys.to_merkle_root();
+        assert_eq!(root, Committee::Addresses(addresses()).to_merkle_root());
+        assert_eq!(root.len(), 4);
+        assert_eq!(root.to_hex().len(), 1);
+
+        let key = hex::decode(&test_utils::beacons()[2]).unwrap();
+        let public_key = *array_ref![key, 0, 64];
+        let path = committee_path(&addresses(), 2);
+        assert!(root.is_signer(2, &public_key, &path));
+        assert!(!root.is_signer(1, &public_key, &path));
+        assert!(!root.is_signer(2, &public_key, &path[..1]));
+        assert!(!root.is_signer(6, &public_key, &path));
+
+        // odd committees pad leaves with zero hashes
+        let three = Committee::Addresses(addresses()[..3].to_vec()).to_merkle_root();
+        assert!(three.is_signer(2, &public_key, &committee_path(&addresses()[..3], 2)));
+    }
+
+    #[test]
+    #[should_panic(expected = "merkle tree root is not match")]
+    fn test_verify_merkle_root_committee() {
+        testing_env!(VMContextBuilder::new().build());
+        let root = Committee::from_hex(&test_utils::beacons()).to_merkle_root();
+        verify_inst(&request_with_paths(), root, &Quorum::default());
+    }
+
+    #[test]
+    #[should_panic(expected = "Signer paths are required for merkle root committee")]
+    fn test_verify_merkle_root_without_paths() {
+        testing_env!(VMContextBuilder::new().build());
+        let root = Committee::from_hex(&test_utils::beacons()).to_merkle_root();
+        verify_inst(&test_utils::signed_request(), root, &Quorum::default());
+    }
+
+    #[test]
+    fn test_compact_committee() {
+        let mut vault = setup();
+        vault.compact_committee(10, false);
+        let root = Committee::PublicKeys(vec![[0; 64]]).to_merkle_root();
+        assert_eq!(vault.beacons.get(&10), Some(root.clone()));
+        assert_eq!(vault.beacons.get(&100), Some(Committee::Addresses(vec![[1; 20]])));
+        assert_eq!(get_logs(), vec![format!(
+            "committee root beacon 10 {} {}",
+            root.to_hex()[0],
+            hex::encode(to_address(&[0; 64])),
+        )]);
+
+        // compacting a root again keeps it and logs nothing
+        vault.compact_committee(10, false);
+        assert_eq!(vault.beacons.get(&10), Some(root));
+        assert_eq!(get_logs().len(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only admin can call this method")]
+    fn test_compact_committee_not_admin() {
+        let mut vault = setup();
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).build());
+        vault.compact_committee(10, false);
+    }
+
+    #[test]
+    #[should_panic(expected = "merkle tree root is not match")]
+    fn test_verified_root_skips_signatures() {
+        let mut vault = setup();
+        let mut request = test_utils::signed_request();
+        vault.verified_roots.insert(&root_key(&request, false, &vault.quorum));
+        request.signatures = vec![];
+        vault.verify_committees(&request, &None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid beacon signature")]
+    fn test_verified_root_bound_to_quorum() {
+        let mut vault = setup();
+        let request = test_utils::signed_request();
+        vault.verified_roots.insert(&root_key(&request, false, &vault.quorum));
+        vault.quorum.height = 5;
+        vault.verify_committees(&request, &None);
+    }
+
+    #[test]
+    fn test_gas_by_committee_mode() {
+        // signature stage for the 4 testnet beacons, committee read from state included
+        let mut vault = setup();
+        let gas = |vault: &Vault, height: u128, request: &InteractRequest| {
+            let start = env::used_gas().0;
+            verify_signatures(request, &vault.get_committee(height), &Quorum::default()).unwrap();
+            env::used_gas().0 - start
+        };
+        vault.beacons.insert(&400, &Committee::from_hex(&test_utils::beacons()));
+        vault.beacons.insert(&500, &Committee::Addresses(addresses()));
+        vault.beacons.insert(&600, &Committee::Addresses(addresses()).to_merkle_root());
+        let keys = gas(&vault, 400, &test_utils::signed_request());
+        let addresses = gas(&vault, 500, &test_utils::signed_request());
+        let root = gas(&vault, 600, &request_with_paths());
+
+        // ecrecover dominates, membership checks stay within a few percent of it
+        assert!(addresses < keys + keys / 20);
+        assert!(root < keys + keys / 20);
+    }
+
+    /// committee of `size` generated keys with `signers` of them signing `signed_request`'s block
+    fn generated_committee(size: usize, signers: usize) -> (Vec<[u8; 64]>, InteractRequest) {
+        let (secret_keys, keys) = test_utils::generated_keys(size);
+        let mut request = test_utils::signed_request();
+        test_utils::sign_request(&mut request, &secret_keys[..signers]);
+        let keys = keys.iter()
+            .map(|key| <[u8; 64]>::try_from(hex::decode(key).unwrap()).unwrap())
+            .collect();
+        (keys, request)
+    }
+
+    #[test]
+    fn test_gas_by_committee_size() {
+        // 32 beacons and the 22 signers a 2/3 quorum needs, committee read from state included
+        let mut vault = setup();
+        let (keys, mut request) = generated_committee(32, 22);
+        let keys = Committee::PublicKeys(keys);
+        let root = keys.to_merkle_root();
+        let stored = |committee: &Committee| committee.try_to_vec().unwrap().len();
+        let gas = |vault: &Vault, height: u128, request: &InteractRequest| {
+            let start = env::used_gas().0;
+            verify_signatures(request, &vault.get_committee(height), &Quorum::default()).unwrap();
+            env::used_gas().0 - start
+        };
+        vault.beacons.insert(&400, &keys);
+        vault.beacons.insert(&500, &root);
+        let keys_gas = gas(&vault, 400, &request);
+        let addresses = keys.addresses().unwrap();
+        request.signer_paths = request.indexes.iter().map(|index| committee_path(&addresses, *index as usize)).collect();
+        let root_gas = gas(&vault, 500, &request);
+
+        // a root stores 40 bytes instead of 64 per beacon, staking saved on every committee swap
+        assert_eq!(stored(&keys), 4 + 1 + 32 * 64);
+        assert_eq!(stored(&root), 1 + 32 + 8);
+        // verification stays dominated by one ecrecover per signer, paths add 5 keccaks each
+        assert!(root_gas > keys_gas);
+        assert!(root_gas < keys_gas + keys_gas / 2);
+    }
+
+    #[test]
+    fn test_committee_views() {
+        let vault = setup();
+        let page = vault.get_committees(Some(1), Some(2));
+        assert_eq!(page.iter().map(|c| c.height).collect::<Vec<_>>(), vec![100, 200]);
+        assert_eq!(vault.get_committees(None, None).len(), 4);
+        assert_eq!(vault.get_committees_len(), 4);
+        assert_eq!(vault.get_latest_committee(), CommitteeView { height: 300, beacons: vec!["03".repeat(20)] });
+        assert_eq!(vault.get_beacons(199), vec!["01".repeat(20)]);
+        assert_eq!(vault.get_beacons(10), vec!["00".repeat(64)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "No beacon committee stored at or below the given height")]
+    fn test_height_below_genesis() {
+        setup().get_beacons(9);
+    }
+
+    #[test]
+    fn test_prune_committees() {
+        let mut vault = setup();
+        vault.set_committee_retention(150);
+        // committees replaced at or before height 150 go, the one active at 150 stays
+        assert_eq!(vault.prune_committees(10, false), 1);
+        assert_eq!(vault.get_committees(None, None).iter().map(|c| c.height).collect::<Vec<_>>(), vec![100, 200, 300]);
+
+        vault.set_committee_retention(0);
+        assert_eq!(vault.prune_committees(1, false), 1);
+        assert_eq!(vault.prune_committees(10, false), 1);
+        assert_eq!(vault.prune_committees(10, false), 0);
+        assert_eq!(vault.get_latest_committee().height, 300);
+    }
+
+    #[test]
+    fn test_prune_bridge_committees() {
+        let mut vault = setup();
+        for height in [50, 150, 250] {
+            vault.bridge_committees.insert(&height, &Committee::Addresses(vec![[9; 20]]));
+        }
+        vault.set_committee_retention(100);
+        assert_eq!(vault.prune_committees(10, true), 1);
+        assert_eq!(vault.get_bridge_committees(None, None).iter().map(|c| c.height).collect::<Vec<_>>(), vec![150, 250]);
+        // beacon committees are left alone
+        assert_eq!(vault.get_committees_len(), 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "No bridge committee stored")]
+    fn test_prune_bridge_committees_empty() {
+        setup().prune_committees(1, true);
+    }
+
+    #[test]
+    fn test_quorum_boundaries() {
+        let quorum = Quorum::default();
+        for (committee_size, needed) in [(4, 3), (7, 5), (22, 15)] {
+            assert!(!quorum.is_reached(needed - 1, committee_size));
+            assert!(quorum.is_reached(needed, committee_size));
+        }
+
+        let quorum = Quorum { numerator: 3, denominator: 4, min_signers: 6, height: 0 };
+        assert!(!quorum.is_reached(3, 4));
+        assert!(!quorum.is_reached(4, 4));
+        assert!(!quorum.is_reached(5, 7));
+        assert!(quorum.is_reached(6, 7));
+        assert!(!quorum.is_reached(16, 22));
+        assert!(quorum.is_reached(17, 22));
+    }
+
+    /// vault of 4 generated beacons and their signed request setting the quorum
+    fn quorum_request(numerator: u64, denominator: u64, min_signers: u64) -> (Vault, InteractRequest) {
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(0)).build());
+        let (secret_keys, keys) = test_utils::generated_keys(4);
+        let vault = Vault::new(keys, 0);
+        let mut inst = vec![SET_QUORUM_METADATA, DEFAULT_SHARD_ID];
+        for value in [numerator, denominator, min_signers] {
+            inst.extend_from_slice(&value.to_be_bytes());
+        }
+        let mut request = test_utils::inst_request(&inst);
+        test_utils::sign_request(&mut request, &secret_keys);
+        (vault, request)
+    }
+
+    #[test]
+    fn test_set_quorum_storage() {
+        let (mut vault, request) = quorum_request(3, 4, 3);
+        test_utils::attach_storage_deposit();
+        let before = env::storage_usage();
+        let quorum = vault.set_quorum(request, None);
+        assert_eq!((quorum.numerator, quorum.denominator, quorum.min_signers), (3, 4, 3));
+        // the cached root is charged to the caller
+        assert_eq!(vault.verified_roots.len(), 1);
+        assert_eq!(vault.storage_usage.committees, env::storage_usage() - before);
+    }
+
+    #[test]
+    fn test_set_quorum_simple_majority() {
+        let (mut vault, request) = quorum_request(1, 2, 1);
+        test_utils::attach_storage_deposit();
+        assert_eq!(vault.set_quorum(request, None).numerator, 1);
+    }
+
+    #[test]
+    fn test_set_quorum_large_terms() {
+        // doubling the numerator must not overflow
+        let (mut vault, request) = quorum_request(u64::MAX - 1, u64::MAX, 1);
+        test_utils::attach_storage_deposit();
+        assert_eq!(vault.set_quorum(request, None).numerator, u64::MAX - 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Quorum must be a majority below the whole committee")]
+    fn test_set_quorum_below_majority() {
+        let (mut vault, request) = quorum_request(u64::MAX / 2, u64::MAX, 1);
+        test_utils::attach_storage_deposit();
+        vault.set_quorum(request, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Attached deposit does not cover storage used by this call")]
+    fn test_set_quorum_without_storage_deposit() {
+        let (mut vault, request) = quorum_request(3, 4, 3);
+        vault.set_quorum(request, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "No bridge committee stored at or below the given height")]
+    fn test_dual_policy_needs_bridge_committee() {
+        setup().set_verification_policy(VerificationPolicy::BeaconAndBridge);
+    }
+
+    #[test]
+    #[should_panic(expected = "Bridge committee proof is required")]
+    fn test_dual_policy_needs_bridge_proof() {
+        let mut vault = setup();
+        vault.bridge_committees.insert(&0, &Committee::from_hex(&test_utils::beacons()));
+        vault.set_verification_policy(VerificationPolicy::BeaconAndBridge);
+        assert_eq!(vault.get_verification_policy(), VerificationPolicy::BeaconAndBridge);
+        vault.verify_committees(&test_utils::signed_request(), &None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Bridge proof instruction differs from beacon proof instruction")]
+    fn test_dual_policy_same_instruction() {
+        let mut vault = setup();
+        vault.bridge_committees.insert(&0, &Committee::from_hex(&test_utils::beacons()));
+        vault.set_verification_policy(VerificationPolicy::BeaconAndBridge);
+        let mut bridge_info = test_utils::signed_request();
+        bridge_info.inst = "00".to_string();
+        vault.verify_committees(&test_utils::signed_request(), &Some(bridge_info));
+    }
+
+    #[test]
+    fn test_bridge_committee_views() {
+        let mut vault = setup();
+        vault.bridge_committees.insert(&5, &Committee::Addresses(vec![[5; 20]]));
+        vault.bridge_committees.insert(&50, &Committee::Addresses(vec![[6; 20]]));
+        assert_eq!(vault.get_bridge_committee(49), vec!["05".repeat(20)]);
+        assert_eq!(vault.get_bridge_committees(Some(1), None)[0].height, 50);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only admin can call this method")]
+    fn test_prune_committees_not_admin() {
+        let mut vault = setup();
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).build());
+        vault.prune_committees(1, false);
+    }
+}
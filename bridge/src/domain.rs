@@ -0,0 +1,118 @@
+use near_sdk::{env, near_bindgen};
+
+use crate::errors::*;
+use crate::utils::{DOMAIN_HEADER_LEN, DOMAIN_INST_VERSION};
+use crate::*;
+
+#[near_bindgen]
+impl Vault {
+    // getters
+
+    /// get domain versioned instructions must carry to be accepted by this vault, hex encoded
+    pub fn get_domain(&self) -> String {
+        hex::encode(self.domain())
+    }
+
+    /// get chain id bound into the domain
+    pub fn get_chain_id(&self) -> String {
+        self.chain_id.clone()
+    }
+
+    /// get NEAR block height legacy instructions stop being accepted at, none when still accepted
+    pub fn get_legacy_inst_sunset(&self) -> Option<u64> {
+        self.legacy_inst_sunset
+    }
+
+    // setters
+
+    /// set chain id bound into the domain, e.g. `mainnet` or `testnet`
+    pub fn set_chain_id(&mut self, chain_id: String) {
+        self.assert_admin();
+        self.chain_id = chain_id;
+    }
+
+    /// reject instructions without domain from NEAR block `height` on, none to keep accepting them
+    pub fn set_legacy_inst_sunset(&mut self, height: Option<u64>) {
+        self.assert_admin();
+        self.legacy_inst_sunset = height;
+    }
+}
+
+impl Vault {
+    /// keccak256 of chain id and vault account, binding instructions to this deployment
+    pub(crate) fn domain(&self) -> [u8; 32] {
+        let mut data = self.chain_id.as_bytes().to_vec();
+        data.push(b':');
+        data.extend_from_slice(env::current_account_id().as_bytes());
+        env::keccak256_array(&data)
+    }
+
+    /// check the domain of a versioned instruction and strip it to the legacy layout,
+    /// legacy instructions pass unchanged until the sunset height
+    pub(crate) fn strip_domain(&self, inst: Vec<u8>) -> Result<Vec<u8>, &'static str> {
+        if inst.first() != Some(&DOMAIN_INST_VERSION) {
+            return match self.legacy_inst_sunset {
+                Some(sunset) if env::block_height() >= sunset => Err(LEGACY_INST_SUNSET),
+                _ => Ok(inst),
+            };
+        }
+        let domain = inst.get(1..DOMAIN_HEADER_LEN).ok_or(INVALID_INSTRUCTION)?;
+        if domain != self.domain() {
+            return Err(DOMAIN_MISMATCH);
+        }
+
+        Ok(inst[DOMAIN_HEADER_LEN..].to_vec())
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    fn setup() -> Vault {
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(0)).build());
+        let mut vault = Vault::new(test_utils::beacons(), 0);
+        vault.set_chain_id("testnet".to_string());
+        vault.token_decimals.insert(&"token.near".to_string(), &9);
+        vault
+    }
+
+    fn with_domain(domain: [u8; 32], inst: &[u8]) -> Vec<u8> {
+        [&[DOMAIN_INST_VERSION], &domain[..], inst].concat()
+    }
+
+    #[test]
+    fn test_withdraw_with_domain() {
+        let mut vault = setup();
+        let inst = with_domain(vault.domain(), &test_utils::withdraw_inst("token.near", "alice.near", 10, [1; 32]));
+        let request = test_utils::shielded_request(&mut vault, &inst);
+        test_utils::attach_storage_deposit();
+        vault.withdraw(request, None);
+        assert!(vault.tx_burn.get(&[1; 32]).unwrap_or_default());
+    }
+
+    #[test]
+    #[should_panic(expected = "Instruction is bound to another vault")]
+    fn test_withdraw_other_domain() {
+        let mut vault = setup();
+        let domain = vault.domain();
+        vault.set_chain_id("mainnet".to_string());
+        let inst = with_domain(domain, &test_utils::withdraw_inst("token.near", "alice.near", 10, [1; 32]));
+        let request = test_utils::shielded_request(&mut vault, &inst);
+        vault.withdraw(request, None);
+    }
+
+    #[test]
+    fn test_legacy_inst_sunset() {
+        let mut vault = setup();
+        vault.set_legacy_inst_sunset(Some(100));
+        let inst = test_utils::withdraw_inst("token.near", "alice.near", 10, [1; 32]);
+        assert_eq!(vault.strip_domain(inst.clone()), Ok(inst.clone()));
+
+        testing_env!(VMContextBuilder::new().block_index(100).build());
+        assert_eq!(vault.strip_domain(inst), Err(LEGACY_INST_SUNSET));
+    }
+}
@@ -1,6 +1,6 @@
 pub const ERR28_WRONG_MSG_FORMAT: &str = "E28: Illegal msg in ft_transfer_call";
 pub const INVALID_MESSAGE: &str = "Message in FT transfer must not be empty";
-pub const _INVALID_INSTRUCTION: &str = "Invalid instruction length";
+pub const INVALID_INSTRUCTION: &str = "Invalid instruction length";
 pub const INVALID_METADATA: &str = "Invalid data in instruction";
 pub const INVALID_KEY_AND_INDEX: &str = "Invalid keys and indexes length in proof";
 pub const INVALID_BEACON_LIST: &str = "Beacons is empty";
@@ -10,4 +10,42 @@ pub const INVALID_TX_BURN: &str = "Transaction burn already used";
 pub const INVALID_MERKLE_TREE: &str = "merkle tree root is not match";
 pub const VALUE_EXCEEDED: &str = "the total balance greater than max value allowed to shield";
 pub const PREV_COMMITTEE_HEIGHT_MISMATCH: &str = "Previous committee height mismatch";
-pub const COMMITTEE_HEIGHT_MISMATCH: &str = "Committee height mismatch";
\ No newline at end of file
+pub const COMMITTEE_HEIGHT_MISMATCH: &str = "Committee height mismatch";
+pub const BEACON_HEIGHT_NOT_FOUND: &str = "No beacon committee stored at or below the given height";
+pub const NOT_ADMIN: &str = "Only admin can call this method";
+pub const INVALID_BEACON_KEY: &str = "Beacon keys must all be 64 bytes public keys or 20 bytes addresses";
+pub const BRIDGE_COMMITTEE_NOT_FOUND: &str = "No bridge committee stored at or below the given height";
+pub const MISSING_BRIDGE_PROOF: &str = "Bridge committee proof is required";
+pub const MISSING_SIGNER_PATHS: &str = "Signer paths are required for merkle root committee";
+pub const BRIDGE_INST_MISMATCH: &str = "Bridge proof instruction differs from beacon proof instruction";
+pub const DUPLICATE_BEACON_SIGNATURE: &str = "Duplicate beacon signature index";
+pub const QUORUM_HEIGHT_MISMATCH: &str = "Quorum instruction must be newer than the current quorum";
+pub const INVALID_RECEIVER: &str = "Invalid receiver account";
+pub const UNKNOWN_TOKEN: &str = "Token was never deposited to vault";
+pub const AMOUNT_OVERFLOW: &str = "Amount overflow";
+pub const BATCH_PROOF_MISMATCH: &str = "Bridge proofs must pair with unshield proofs";
+pub const TRANSFER_FAILED: &str = "Transfer failed";
+pub const NATIVE_TRANSFER_CALL: &str = "Native NEAR cannot be withdrawn with a message";
+pub const INVALID_RELAYER_FEE: &str = "Relayer fee exceeds unshield amount";
+pub const INVALID_FEE: &str = "Fee must not exceed 1000 basis points";
+pub const NOT_TREASURY: &str = "Only treasury can claim fees";
+pub const INVALID_FEE_CLAIM: &str = "Claim must be positive and within accrued fees";
+pub const NFT_COLLECTION_NOT_ALLOWED: &str = "NFT collection is not allowed";
+pub const NFT_NOT_LOCKED: &str = "NFT is not locked in vault";
+pub const NOTHING_TO_CLAIM: &str = "No refund to claim";
+pub const INVALID_QUORUM: &str = "Quorum must be a majority below the whole committee";
+pub const MT_TOKEN_NOT_REGISTERED: &str = "Multi token id is not registered";
+pub const INVALID_TX_ID: &str = "Burn tx id must be 32 bytes in hex or base58";
+pub const NO_SURPLUS: &str = "No surplus to recover";
+pub const INVALID_MT_BATCH: &str = "Token ids and amounts must have the same length";
+pub const DOMAIN_MISMATCH: &str = "Instruction is bound to another vault";
+pub const LEGACY_INST_SUNSET: &str = "Instructions without domain are no longer accepted";
+pub const STORAGE_DEPOSIT_REQUIRED: &str = "Attached deposit does not cover storage used by this call";
+pub const MT_BALANCE_UNAVAILABLE: &str = "Unable to read multi token balances of the vault";
+pub const LEDGER_NOT_INITIALIZED: &str = "Locked balance of this token must be initialized after migrating";
+pub const LEDGER_ALREADY_INITIALIZED: &str = "Locked balance of this token is already tracked";
+pub const LOCKED_BALANCE_EXCEEDED: &str = "Payout exceeds the locked balance of this token";
+pub const TOO_MANY_TX_IDS: &str = "Too many burn tx ids in one query";
+pub const NOT_VAULT_CALLBACK: &str = "Only the vault can call this method";
+pub const TOKEN_BALANCE_UNAVAILABLE: &str = "Unable to read token metadata or balance of the vault";
+pub const RECOVERY_IN_PROGRESS: &str = "A surplus recovery of this token has not settled yet";
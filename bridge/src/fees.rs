@@ -0,0 +1,205 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, AccountId, Gas, Promise};
+use near_sdk::json_types::U128;
+
+use crate::errors::*;
+use crate::utils::{split_mt_token_key, MAX_FEE_BPS, NEAR_ADDRESS};
+use crate::*;
+
+const BPS_DENOMINATOR: u128 = 10_000;
+
+/// Protocol fees of one token in basis points, native NEAR is keyed by `NEAR_ADDRESS`.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenFee {
+    pub shield_bps: u16,
+    pub unshield_bps: u16,
+}
+
+#[near_bindgen]
+impl Vault {
+    // getters
+
+    /// get protocol fees of token
+    pub fn get_token_fee(&self, token: String) -> TokenFee {
+        self.token_fees.get(&token).unwrap_or_default()
+    }
+
+    /// get fees accrued for token, in the token's own decimals or yoctoNEAR
+    pub fn get_accrued_fees(&self, token: String) -> U128 {
+        U128(self.accrued_fees.get(&token).unwrap_or_default())
+    }
+
+    /// get account allowed to claim fees
+    pub fn get_treasury(&self) -> AccountId {
+        self.treasury.clone()
+    }
+
+    // setters
+
+    /// set protocol fees of token, `NEAR_ADDRESS` for native NEAR
+    pub fn set_token_fee(&mut self, token: String, fee: TokenFee) {
+        self.assert_admin();
+        assert!(fee.shield_bps <= MAX_FEE_BPS && fee.unshield_bps <= MAX_FEE_BPS, "{}", INVALID_FEE);
+        self.token_fees.insert(&token, &fee);
+    }
+
+    /// hand fee claims over to another account
+    pub fn set_treasury(&mut self, treasury: AccountId) {
+        self.assert_admin();
+        self.treasury = treasury;
+    }
+
+    /// claim fees
+    ///
+    /// transfer fees accrued for token to the treasury, all of them unless `amount` is given
+    pub fn claim_fees(&mut self, token: String, amount: Option<U128>) -> Promise {
+        assert_eq!(env::predecessor_account_id(), self.treasury, "{}", NOT_TREASURY);
+        let accrued = self.accrued_fees.get(&token).unwrap_or_default();
+        let amount = amount.map(|amount| amount.0).unwrap_or(accrued);
+        assert!(amount > 0 && amount <= accrued, "{}", INVALID_FEE_CLAIM);
+        self.accrued_fees.insert(&token, &(accrued - amount));
+        self.add_withdrawing(&token, amount);
+
+        let transfer = if token == NEAR_ADDRESS {
+            Promise::new(self.treasury.clone()).transfer(amount)
+        } else if let Some((contract, token_id)) = split_mt_token_key(&token) {
+            ext_mt::ext(AccountId::try_from(contract.to_string()).expect(INVALID_METADATA))
+                .with_attached_deposit(1)
+                .with_static_gas(Gas(10_000_000_000_000))
+                .mt_transfer(
+                    self.treasury.clone(),
+                    token_id.to_string(),
+                    U128(amount),
+                    None,
+                    None,
+                )
+        } else {
+            ext_ft::ext(AccountId::try_from(token.clone()).expect(INVALID_METADATA))
+                .with_attached_deposit(1)
+                .with_static_gas(Gas(5_000_000_000_000))
+                .ft_transfer(
+                    self.treasury.clone(),
+                    U128(amount),
+                    None,
+                )
+        };
+        transfer.then(ext_self::ext(env::current_account_id())
+            .with_static_gas(Gas(5_000_000_000_000))
+            .on_claim_fees(
+                token,
+                U128(amount),
+            ))
+    }
+
+    /// settle a fee claim, restoring fees whose transfer failed
+    #[private]
+    pub fn on_claim_fees(&mut self, token: String, amount: U128) {
+        self.remove_withdrawing(&token, amount.0);
+        if !promise_succeeded(0) {
+            self.accrue_fee(&token, amount.0);
+        }
+    }
+}
+
+impl Vault {
+    /// protocol fee of `amount` of token
+    pub(crate) fn protocol_fee(&self, token: &str, amount: u128, shield: bool) -> u128 {
+        let fee = self.token_fees.get(&token.to_string()).unwrap_or_default();
+        let bps = if shield { fee.shield_bps } else { fee.unshield_bps };
+        // split so wide amounts cannot overflow
+        amount / BPS_DENOMINATOR * bps as u128 + amount % BPS_DENOMINATOR * bps as u128 / BPS_DENOMINATOR
+    }
+
+    /// protocol fee of `amount` of token, accrued to the fee ledger
+    pub(crate) fn take_fee(&mut self, token: &str, amount: u128, shield: bool) -> u128 {
+        let fee = self.protocol_fee(token, amount, shield);
+        if fee > 0 {
+            self.accrue_fee(token, fee);
+        }
+
+        fee
+    }
+
+    pub(crate) fn accrue_fee(&mut self, token: &str, amount: u128) {
+        let accrued = self.accrued_fees.get(&token.to_string()).unwrap_or_default();
+        self.accrued_fees.insert(&token.to_string(), &(accrued + amount));
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use near_sdk::mock::VmAction;
+    use near_sdk::serde_json;
+    use near_sdk::test_utils::{accounts, get_created_receipts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    fn setup() -> Vault {
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(0)).build());
+        let mut vault = Vault::new(vec!["00".repeat(64)], 10);
+        vault.set_token_fee("token.near".to_string(), TokenFee { shield_bps: 30, unshield_bps: 100 });
+        vault
+    }
+
+    #[test]
+    fn test_take_fee() {
+        let mut vault = setup();
+        assert_eq!(vault.take_fee("token.near", 10_000, true), 30);
+        assert_eq!(vault.take_fee("token.near", 10_000, false), 100);
+        assert_eq!(vault.take_fee("other.near", 10_000, false), 0);
+        assert_eq!(vault.get_accrued_fees("token.near".to_string()), U128(130));
+    }
+
+    #[test]
+    #[should_panic(expected = "Fee must not exceed 1000 basis points")]
+    fn test_set_token_fee_above_max() {
+        let mut vault = setup();
+        vault.set_token_fee("token.near".to_string(), TokenFee { shield_bps: 1001, unshield_bps: 0 });
+    }
+
+    #[test]
+    fn test_claim_fees() {
+        let mut vault = setup();
+        vault.take_fee("token.near", 10_000, false);
+        vault.claim_fees("token.near".to_string(), Some(U128(40)));
+        assert_eq!(vault.get_accrued_fees("token.near".to_string()), U128(60));
+        vault.claim_fees("token.near".to_string(), None);
+        assert_eq!(vault.get_accrued_fees("token.near".to_string()), U128(0));
+    }
+
+    #[test]
+    fn test_claim_mt_fees() {
+        let mut vault = setup();
+        let token_key = crate::utils::mt_token_key(accounts(2).as_str(), "gold");
+        vault.accrue_fee(&token_key, 50);
+        drop(vault.claim_fees(token_key.clone(), None));
+        assert_eq!(vault.get_accrued_fees(token_key), U128(0));
+
+        let receipts = get_created_receipts();
+        assert_eq!(receipts[0].receiver_id, accounts(2));
+        assert!(matches!(
+            &receipts[0].actions[0],
+            VmAction::FunctionCall { function_name, args, .. } if function_name == "mt_transfer"
+                && serde_json::from_slice::<serde_json::Value>(args).unwrap()["token_id"] == "gold"
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only treasury can claim fees")]
+    fn test_claim_fees_not_treasury() {
+        let mut vault = setup();
+        vault.take_fee("token.near", 10_000, false);
+        vault.set_treasury(accounts(1));
+        vault.claim_fees("token.near".to_string(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Claim must be positive and within accrued fees")]
+    fn test_claim_fees_above_accrued() {
+        let mut vault = setup();
+        vault.take_fee("token.near", 10_000, false);
+        vault.claim_fees("token.near".to_string(), Some(U128(101)));
+    }
+}
@@ -0,0 +1,178 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen};
+
+use crate::inst::WithdrawInst;
+use crate::utils::{MAX_BURN_PAGE, MT_WITHDRAW_METADATA, NFT_WITHDRAW_METADATA};
+use crate::*;
+
+/// Processed burn proof, kept in the order proofs were accepted.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BurnRecord {
+    pub index: u64,
+    // hex encoded burn tx id
+    pub tx_id: String,
+    // instruction metadata type
+    pub meta_type: u8,
+    pub token: String,
+    // token id of NFT and multi token unshields
+    pub token_id: Option<String>,
+    pub receiver: String,
+    // in Incognito's 9 decimals
+    pub amount: U128,
+    pub block_height: u64,
+    // set when the release failed and the burn can be submitted again
+    pub reverted: bool,
+}
+
+/// Page of `get_burns`, filtered pages may be empty before the end of the history.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BurnPage {
+    pub records: Vec<BurnRecord>,
+    // index to continue from, none once the whole history was scanned
+    pub next_index: Option<u64>,
+}
+
+#[near_bindgen]
+impl Vault {
+    // getters
+
+    /// get number of processed burns
+    pub fn get_burn_count(&self) -> u64 {
+        self.burn_history.len()
+    }
+
+    /// get up to `limit` processed burns from `from_index` on, only of `token` and `receiver` when given
+    ///
+    /// at most `MAX_BURN_PAGE` records are scanned per call, continue from `next_index` until it is none.
+    /// a `limit` of 0 returns up to a full page
+    pub fn get_burns(
+        &self,
+        from_index: u64,
+        limit: u64,
+        token: Option<String>,
+        receiver: Option<String>,
+    ) -> BurnPage {
+        let end = self.burn_history.len().min(from_index.saturating_add(MAX_BURN_PAGE));
+        let limit = match limit {
+            0 => MAX_BURN_PAGE,
+            limit => limit.min(MAX_BURN_PAGE),
+        } as usize;
+        let mut records = vec![];
+        let mut next_index = from_index.max(end);
+        for index in from_index..end {
+            if records.len() == limit {
+                next_index = index;
+                break;
+            }
+            let record = self.burn_history.get(index)
+                .filter(|record| token.iter().all(|token| &record.token == token))
+                .filter(|record| receiver.iter().all(|receiver| &record.receiver == receiver));
+            records.extend(record);
+        }
+
+        BurnPage {
+            records,
+            next_index: (next_index < self.burn_history.len()).then_some(next_index),
+        }
+    }
+}
+
+impl Vault {
+    /// append accepted burn to history, returns its index
+    pub(crate) fn record_burn(&mut self, inst: &WithdrawInst) -> u64 {
+        let token_id = match inst.meta_type {
+            NFT_WITHDRAW_METADATA | MT_WITHDRAW_METADATA => inst.payload.clone(),
+            _ => None,
+        };
+        let index = self.burn_history.len();
+        self.burn_history.push(&BurnRecord {
+            index,
+            tx_id: hex::encode(inst.tx_id),
+            meta_type: inst.meta_type,
+            token: inst.token.clone(),
+            token_id,
+            receiver: inst.receiver.clone(),
+            amount: U128(inst.amount),
+            block_height: env::block_height(),
+            reverted: false,
+        });
+
+        index
+    }
+
+    /// mark burn whose release failed
+    pub(crate) fn revert_burn(&mut self, index: u64) {
+        if let Some(mut record) = self.burn_history.get(index) {
+            record.reverted = true;
+            self.burn_history.replace(index, &record);
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    #[test]
+    fn test_get_burns() {
+        testing_env!(VMContextBuilder::new().block_index(7).build());
+        let mut vault = Vault::new(test_utils::beacons(), 0);
+        vault.token_decimals.insert(&"token.near".to_string(), &9);
+        vault.token_decimals.insert(&"other.near".to_string(), &9);
+        let burns = [("token.near", "alice.near"), ("other.near", "alice.near"), ("token.near", "bob.near")];
+        for (i, (token, receiver)) in burns.iter().enumerate() {
+            let inst = test_utils::withdraw_inst(token, receiver, 5, [i as u8; 32]);
+            let request = test_utils::shielded_request(&mut vault, &inst);
+            test_utils::attach_storage_deposit();
+            vault.withdraw(request, None);
+        }
+        assert_eq!(vault.get_burn_count(), 3);
+
+        let all = vault.get_burns(0, 10, None, None).records;
+        assert_eq!(all.len(), 3);
+        assert_eq!((all[2].index, all[2].tx_id.clone(), all[2].amount, all[2].block_height), (2, hex::encode([2u8; 32]), U128(5), 7));
+
+        let by_token = vault.get_burns(0, 10, Some("token.near".to_string()), None).records;
+        assert_eq!(by_token.iter().map(|record| record.index).collect::<Vec<_>>(), vec![0, 2]);
+        let by_receiver = vault.get_burns(1, 1, None, Some("alice.near".to_string())).records;
+        assert_eq!(by_receiver.iter().map(|record| record.index).collect::<Vec<_>>(), vec![1]);
+    }
+
+    #[test]
+    fn test_get_burns_paging() {
+        testing_env!(VMContextBuilder::new().build());
+        let mut vault = Vault::new(test_utils::beacons(), 0);
+        for i in 0..MAX_BURN_PAGE + 2 {
+            let receiver = if i == MAX_BURN_PAGE + 1 { "bob.near" } else { "alice.near" };
+            let mut tx_id = [0u8; 32];
+            tx_id[..8].copy_from_slice(&i.to_be_bytes());
+            vault.record_burn(&crate::inst::decode_withdraw(&test_utils::withdraw_inst("token.near", receiver, 5, tx_id)).unwrap());
+        }
+
+        // nothing matches in the first scanned page, which is not the end of the history
+        let page = vault.get_burns(0, 10, None, Some("bob.near".to_string()));
+        assert!(page.records.is_empty());
+        assert_eq!(page.next_index, Some(MAX_BURN_PAGE));
+        let page = vault.get_burns(MAX_BURN_PAGE, 10, None, Some("bob.near".to_string()));
+        assert_eq!(page.records.iter().map(|record| record.index).collect::<Vec<_>>(), vec![MAX_BURN_PAGE + 1]);
+        assert_eq!(page.next_index, None);
+
+        // a full page continues right after its last record
+        let page = vault.get_burns(0, 2, None, None);
+        assert_eq!(page.records.len(), 2);
+        assert_eq!(page.next_index, Some(2));
+        assert_eq!(vault.get_burns(MAX_BURN_PAGE + 2, 10, None, None).next_index, None);
+
+        // no limit still moves on instead of returning `from_index` forever
+        let page = vault.get_burns(0, 0, None, None);
+        assert_eq!(page.records.len() as u64, MAX_BURN_PAGE);
+        assert_eq!(page.next_index, Some(MAX_BURN_PAGE));
+    }
+}
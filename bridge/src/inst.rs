@@ -0,0 +1,237 @@
+use std::convert::TryInto;
+
+use crate::errors::*;
+use crate::utils::{
+    parse_relayer_fee, parse_withdraw_payload, BURN_METADATA, MT_WITHDRAW_METADATA, NFT_WITHDRAW_METADATA,
+    WITHDRAW_CALL_METADATA, WITHDRAW_FEE_LEN, WITHDRAW_FEE_METADATA, WITHDRAW_MEMO_METADATA,
+    WITHDRAW_METADATA, WITHDRAW_PAYLOAD_LEN, WITHDRAW_WIDE_METADATA,
+};
+
+/// Withdraw or burn instruction decoded from any registered layout.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WithdrawInst {
+    pub meta_type: u8,
+    pub shard_id: u8,
+    pub token: String,
+    pub receiver: String,
+    // in Incognito's 9 decimals
+    pub amount: u128,
+    pub tx_id: [u8; 32],
+    // message, memo or token id appended to payload instructions
+    pub payload: Option<String>,
+    // relayer fee appended to withdraw fee instructions, in the unit of the amount
+    pub relayer_fee: u128,
+}
+
+/// Fixed part of a withdraw instruction: metadata, shard id, left padded token and
+/// receiver each after their length byte, big endian amount and burn tx id.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WithdrawLayout {
+    pub account_len: usize,
+    pub amount_len: usize,
+    // whole amount field is read, otherwise only its low 8 bytes
+    pub wide_amount: bool,
+}
+
+impl WithdrawLayout {
+    pub const fn len(&self) -> usize {
+        1 + 1 + 1 + self.account_len + 1 + self.account_len + self.amount_len + 32
+    }
+}
+
+/// Data appended after the fixed part.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Trailer {
+    None,
+    // 2 bytes length then utf8 string
+    Payload,
+    // 8 bytes relayer fee
+    Fee,
+}
+
+/// Decoder of one layout version of one metadata type.
+///
+/// `exact` layouts only match instructions of their exact length, others accept
+/// trailing bytes and are tried after every exact layout of the metadata type.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InstDecoder {
+    pub meta_type: u8,
+    pub layout: WithdrawLayout,
+    pub trailer: Trailer,
+    pub exact: bool,
+}
+
+// first release layout, Incognito appends 64 bytes the vault ignores
+pub const LEGACY_WITHDRAW_LAYOUT: WithdrawLayout = WithdrawLayout { account_len: 64, amount_len: 32, wide_amount: false };
+// legacy layout whose 32 bytes amount is read in full, it must still fit in u128
+pub const WIDE_WITHDRAW_LAYOUT: WithdrawLayout = WithdrawLayout { wide_amount: true, ..LEGACY_WITHDRAW_LAYOUT };
+
+const fn legacy(meta_type: u8, trailer: Trailer) -> InstDecoder {
+    InstDecoder { meta_type, layout: LEGACY_WITHDRAW_LAYOUT, trailer, exact: false }
+}
+
+/// registered withdraw layouts, new versions are added here keyed by metadata type and length
+pub const WITHDRAW_DECODERS: &[InstDecoder] = &[
+    legacy(WITHDRAW_METADATA, Trailer::None),
+    legacy(WITHDRAW_CALL_METADATA, Trailer::Payload),
+    legacy(WITHDRAW_MEMO_METADATA, Trailer::Payload),
+    legacy(WITHDRAW_FEE_METADATA, Trailer::Fee),
+    legacy(NFT_WITHDRAW_METADATA, Trailer::Payload),
+    legacy(MT_WITHDRAW_METADATA, Trailer::Payload),
+    legacy(BURN_METADATA, Trailer::None),
+    InstDecoder { meta_type: WITHDRAW_WIDE_METADATA, layout: WIDE_WITHDRAW_LAYOUT, trailer: Trailer::None, exact: false },
+];
+
+/// decode withdraw instruction with the registered layouts
+pub fn decode_withdraw(inst: &[u8]) -> Result<WithdrawInst, &'static str> {
+    decode_withdraw_with(inst, WITHDRAW_DECODERS)
+}
+
+/// decode withdraw instruction with the first of `decoders` matching its metadata type and length
+pub fn decode_withdraw_with(inst: &[u8], decoders: &[InstDecoder]) -> Result<WithdrawInst, &'static str> {
+    let meta_type = *inst.first().ok_or(INVALID_INSTRUCTION)?;
+    let candidates: Vec<&InstDecoder> = decoders.iter().filter(|decoder| decoder.meta_type == meta_type).collect();
+    if candidates.is_empty() {
+        return Err(INVALID_METADATA);
+    }
+    candidates.iter()
+        .filter(|decoder| decoder.exact)
+        .chain(candidates.iter().filter(|decoder| !decoder.exact))
+        .find_map(|decoder| decoder.decode(inst))
+        .ok_or(INVALID_INSTRUCTION)?
+}
+
+impl InstDecoder {
+    /// none when `inst` does not have this layout
+    fn decode(&self, inst: &[u8]) -> Option<Result<WithdrawInst, &'static str>> {
+        let len = self.layout.len();
+        let (payload, relayer_fee, trailer_len) = match self.trailer {
+            Trailer::None => (None, 0, 0),
+            Trailer::Payload => {
+                let payload = parse_withdraw_payload(inst, len)?;
+                let payload_len = WITHDRAW_PAYLOAD_LEN + payload.len();
+                (Some(payload), 0, payload_len)
+            }
+            Trailer::Fee => (None, parse_relayer_fee(inst, len)?, WITHDRAW_FEE_LEN),
+        };
+        if inst.len() < len || (self.exact && inst.len() != len + trailer_len) {
+            return None;
+        }
+
+        let account_len = self.layout.account_len;
+        let mut offset = 2;
+        let token = read_account(inst, &mut offset, account_len);
+        let receiver = read_account(inst, &mut offset, account_len);
+        let amount = read_amount(&inst[offset..offset + self.layout.amount_len], self.layout.wide_amount);
+        offset += self.layout.amount_len;
+        let tx_id: [u8; 32] = inst[offset..offset + 32].try_into().ok()?;
+
+        Some(amount.map(|amount| WithdrawInst {
+            meta_type: inst[0],
+            shard_id: inst[1],
+            token,
+            receiver,
+            amount,
+            tx_id,
+            payload,
+            relayer_fee,
+        }))
+    }
+}
+
+/// read big endian amount, of up to 256 bits when `wide` and fitting in u128,
+/// otherwise from its low 8 bytes as legacy instructions always were
+fn read_amount(amount: &[u8], wide: bool) -> Result<u128, &'static str> {
+    if !wide {
+        let low = amount.get(amount.len().saturating_sub(8)..).unwrap_or_default();
+        let mut bytes = [0u8; 8];
+        bytes[8 - low.len()..].copy_from_slice(low);
+        return Ok(u128::from(u64::from_be_bytes(bytes)));
+    }
+    let (high, low) = amount.split_at(amount.len().saturating_sub(16));
+    if high.iter().any(|byte| *byte != 0) {
+        return Err(AMOUNT_OVERFLOW);
+    }
+    let mut bytes = [0u8; 16];
+    bytes[16 - low.len()..].copy_from_slice(low);
+    Ok(u128::from_be_bytes(bytes))
+}
+
+/// read length byte then the left padded account of `account_len` bytes
+fn read_account(inst: &[u8], offset: &mut usize, account_len: usize) -> String {
+    let len = (inst[*offset] as usize).min(account_len);
+    let account = &inst[*offset + 1 + account_len - len..*offset + 1 + account_len];
+    *offset += 1 + account_len;
+    String::from_utf8(account.to_vec()).unwrap_or_default()
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::test_utils::{withdraw_fee_inst, withdraw_inst, withdraw_payload_inst};
+    use crate::utils::SET_QUORUM_METADATA;
+
+    #[test]
+    fn test_decode_legacy() {
+        assert_eq!(LEGACY_WITHDRAW_LAYOUT.len(), 196);
+        let inst = decode_withdraw(&withdraw_inst("token.near", "alice.near", 5, [1; 32])).unwrap();
+        assert_eq!((inst.token.as_str(), inst.receiver.as_str(), inst.amount, inst.tx_id), ("token.near", "alice.near", 5, [1; 32]));
+
+        let inst = decode_withdraw(&withdraw_payload_inst(WITHDRAW_MEMO_METADATA, "token.near", "alice.near", 5, [1; 32], "memo")).unwrap();
+        assert_eq!(inst.payload, Some("memo".to_string()));
+        let inst = decode_withdraw(&withdraw_fee_inst("token.near", "alice.near", 5, [1; 32], 2)).unwrap();
+        assert_eq!(inst.relayer_fee, 2);
+
+        assert_eq!(decode_withdraw(&[WITHDRAW_METADATA, 1]), Err(INVALID_INSTRUCTION));
+        assert_eq!(decode_withdraw(&[SET_QUORUM_METADATA, 1]), Err(INVALID_METADATA));
+    }
+
+    #[test]
+    fn test_decode_legacy_amount() {
+        // legacy instructions keep reading the low 8 bytes only
+        let amount_offset = LEGACY_WITHDRAW_LAYOUT.len() - 64;
+        let mut inst = withdraw_inst("token.near", "alice.near", 5, [1; 32]);
+        inst[amount_offset] = 1;
+        inst[amount_offset + 23] = 1;
+        assert_eq!(decode_withdraw(&inst).unwrap().amount, 5);
+    }
+
+    #[test]
+    fn test_decode_wide_amount() {
+        let amount_offset = WIDE_WITHDRAW_LAYOUT.len() - 64;
+        let mut inst = withdraw_inst("token.near", "alice.near", 0, [1; 32]);
+        inst[0] = WITHDRAW_WIDE_METADATA;
+        inst[amount_offset + 16..amount_offset + 32].copy_from_slice(&(u128::MAX - 1).to_be_bytes());
+        assert_eq!(decode_withdraw(&inst).unwrap().amount, u128::MAX - 1);
+
+        inst[amount_offset + 15] = 1;
+        assert_eq!(decode_withdraw(&inst), Err(AMOUNT_OVERFLOW));
+    }
+
+    #[test]
+    fn test_decode_by_length() {
+        // a longer account layout registered next to the legacy one
+        let long = WithdrawLayout { account_len: 128, amount_len: 32, wide_amount: false };
+        let decoders = [
+            legacy(WITHDRAW_METADATA, Trailer::None),
+            InstDecoder { meta_type: WITHDRAW_METADATA, layout: long, trailer: Trailer::None, exact: true },
+        ];
+        let receiver = "a".repeat(100);
+        let mut inst = vec![WITHDRAW_METADATA, 1, 10];
+        inst.extend_from_slice(&[0; 118]);
+        inst.extend_from_slice(b"token.near");
+        inst.push(100);
+        inst.extend_from_slice(&[0; 28]);
+        inst.extend_from_slice(receiver.as_bytes());
+        inst.extend_from_slice(&[0; 24]);
+        inst.extend_from_slice(&7u64.to_be_bytes());
+        inst.extend_from_slice(&[3; 32]);
+        assert_eq!(inst.len(), long.len());
+
+        let decoded = decode_withdraw_with(&inst, &decoders).unwrap();
+        assert_eq!((decoded.token.as_str(), decoded.receiver, decoded.amount, decoded.tx_id), ("token.near", receiver, 7, [3; 32]));
+        // existing proofs keep decoding with the legacy layout
+        let decoded = decode_withdraw_with(&withdraw_inst("token.near", "alice.near", 5, [1; 32]), &decoders).unwrap();
+        assert_eq!(decoded.receiver, "alice.near");
+    }
+}
@@ -6,21 +6,39 @@ NOTES:
 */
 
 mod token_receiver;
+mod nft_receiver;
+mod mt_receiver;
 mod errors;
 mod utils;
+mod committee;
+mod migrate;
+mod root_cache;
+mod fees;
+mod wrap;
+mod domain;
+mod inst;
+mod shards;
+mod reserves;
+mod history;
+mod storage;
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod test_utils;
 
 use std::str;
-use std::cmp::Ordering;
 use std::convert::{TryFrom, TryInto};
-use near_sdk::{serde_json};
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
 use near_sdk::{env, near_bindgen, BorshStorageKey, PanicOnDefault, ext_contract, PromiseResult, AccountId, Gas, Promise, PromiseOrValue};
 use near_sdk::serde::{Deserialize, Serialize};
-use near_sdk::collections::{LookupMap, TreeMap};
+use near_sdk::collections::{LookupMap, LookupSet, TreeMap, Vector};
 use crate::errors::*;
-use crate::utils::{NEAR_ADDRESS, WITHDRAW_INST_LEN, SWAP_COMMITTEE_INST_LEN, WITHDRAW_METADATA, SWAP_BEACON_METADATA, BURN_METADATA};
-use crate::utils::{verify_inst};
-use arrayref::{array_refs, array_ref};
+use crate::utils::{NEAR_ADDRESS, WITHDRAW_METADATA, WITHDRAW_CALL_METADATA, WITHDRAW_MEMO_METADATA, WITHDRAW_FEE_METADATA, MT_WITHDRAW_METADATA, WITHDRAW_WIDE_METADATA, SWAP_BEACON_METADATA, SWAP_BRIDGE_METADATA, BURN_METADATA, DEFAULT_COMMITTEE_RETENTION, DEFAULT_DEPOSIT_CAP, MAX_BURN_QUERY};
+use crate::utils::{parse_swap_inst, parse_tx_id, incognito_unit, mt_token_key, promise_succeeded, split_mt_token_key, then_after};
+use crate::inst::WithdrawInst;
+use crate::committee::{Committee, Quorum, VerificationPolicy};
+use crate::root_cache::RootCache;
+use crate::fees::TokenFee;
+use crate::storage::Subsystem;
+use arrayref::array_ref;
 use near_contract_standards::fungible_token::metadata::FungibleTokenMetadata;
 use near_sdk::json_types::U128;
 
@@ -45,7 +63,22 @@ pub struct InteractRequest {
     // signatures
     pub signatures: Vec<String>,
     // v value
-    pub vs: Vec<u8>
+    pub vs: Vec<u8>,
+    // committee membership proof per signature, only for merkle root committees
+    #[serde(default)]
+    pub signer_paths: Vec<Vec<[u8; 32]>>,
+}
+
+/// outcome of one proof in `withdraw_batch`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct WithdrawResult {
+    // position of the proof in the batch
+    pub index: u32,
+    // hex burn tx id, set once the proof is accepted
+    pub tx_id: Option<String>,
+    // reason the proof or its transfer failed
+    pub error: Option<String>,
 }
 
 #[derive(BorshStorageKey, BorshSerialize)]
@@ -55,6 +88,23 @@ pub(crate) enum StorageKey {
     TokenAccountID,
     TokenUserAccountID,
     TokenDecimals,
+    BridgeHeight,
+    VerifiedRoot,
+    VerifiedRootKeys,
+    Claimable,
+    TokenFee,
+    AccruedFee,
+    UnwrapNear,
+    NftCollection,
+    LockedNft,
+    DepositCap,
+    ShardIds,
+    LockedBalance,
+    PendingDeposit,
+    BurnHistory,
+    LedgerToken,
+    PendingWithdrawal,
+    Recovering,
 }
 
 #[near_bindgen]
@@ -63,13 +113,63 @@ pub struct Vault {
     // mark tx already burn
     pub tx_burn: LookupMap<[u8; 32], bool>,
     // beacon committees
-    pub beacons: TreeMap<u128, Vec<String>>,
+    pub beacons: TreeMap<u128, Committee>,
+    // bridge committees
+    pub bridge_committees: TreeMap<u128, Committee>,
+    // committees that must sign instructions
+    pub verification_policy: VerificationPolicy,
+    // share of a committee that must sign instructions
+    pub quorum: Quorum,
+    // block roots with verified signatures
+    pub verified_roots: RootCache,
     // total withdraw request
     pub total_credit_amount: LookupMap<String, u128>,
     // total credit amount for each account
     pub credit_amount: LookupMap<(String, String), u128>,
     // store token decimal
     pub token_decimals: LookupMap<String, u8>,
+    // refunds of withdraw calls claimable by receivers
+    pub claimable: LookupMap<(String, String), u128>,
+    // protocol fees per token
+    pub token_fees: LookupMap<String, TokenFee>,
+    // protocol fees collected per token, not part of locked funds
+    pub accrued_fees: LookupMap<String, u128>,
+    // account allowed to claim protocol fees
+    pub treasury: AccountId,
+    // wNEAR contract native deposits are wrapped into
+    pub wrap_near: Option<AccountId>,
+    // receivers of wNEAR unshields asking for native NEAR
+    pub unwrap_near_receivers: LookupSet<AccountId>,
+    // NFT collections allowed to be shielded
+    pub nft_collections: LookupSet<AccountId>,
+    // (collection, token id) of NFTs locked in vault
+    pub locked_nfts: LookupSet<(String, String)>,
+    // highest vault balance per token shields are accepted up to, in Incognito's 9 decimals
+    pub deposit_caps: LookupMap<String, u128>,
+    // tokens owed to Incognito holders or claimants, native NEAR is keyed by `NEAR_ADDRESS`
+    pub locked_balances: LookupMap<String, u128>,
+    // processed burns in the order they were accepted
+    pub burn_history: Vector<history::BurnRecord>,
+    // storage added per subsystem, paid by the callers growing it
+    pub storage_usage: storage::StorageUsage,
+    // tokens received by shields still waiting for their callback
+    pub pending_deposits: LookupMap<String, u128>,
+    // tokens paid out by transfers still waiting for their callback
+    pub pending_withdrawals: LookupMap<String, u128>,
+    // tokens whose locked balance the admin initialized after migrating, none when every token is tracked
+    pub ledger_tokens: Option<LookupSet<String>>,
+    // tokens whose surplus recovery has not settled yet
+    pub recovering: LookupSet<String>,
+    // shard ids accepted per instruction metadata type
+    pub shard_ids: LookupMap<u8, Vec<u8>>,
+    // chain id bound with the vault account into the instruction domain
+    pub chain_id: String,
+    // NEAR block height instructions without domain are rejected from
+    pub legacy_inst_sunset: Option<u64>,
+    // account allowed to manage vault settings
+    pub admin: AccountId,
+    // beacon heights a superseded committee is kept before pruning
+    pub committee_retention: u128,
 }
 
 // define the methods we'll use on ContractB
@@ -78,6 +178,26 @@ pub trait FtContract {
     fn ft_metadata(&self) -> FungibleTokenMetadata;
     fn ft_balance_of(&mut self, account_id: AccountId) -> U128;
     fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>);
+    fn ft_transfer_call(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>, msg: String) -> PromiseOrValue<U128>;
+}
+
+#[ext_contract(ext_nft)]
+pub trait NftContract {
+    fn nft_transfer(&mut self, receiver_id: AccountId, token_id: String, approval_id: Option<u64>, memo: Option<String>);
+}
+
+// NEP-245 multi token contract
+#[ext_contract(ext_mt)]
+pub trait MtContract {
+    fn mt_transfer(&mut self, receiver_id: AccountId, token_id: String, amount: U128, approval: Option<(AccountId, u64)>, memo: Option<String>);
+    fn mt_batch_balance_of(&self, account_id: AccountId, token_ids: Vec<String>) -> Vec<U128>;
+}
+
+// wrapped NEAR contract
+#[ext_contract(ext_wnear)]
+pub trait WrapNear {
+    fn near_deposit(&mut self);
+    fn near_withdraw(&mut self, amount: U128);
 }
 
 // define methods we'll use as callbacks on ContractA
@@ -89,11 +209,32 @@ pub trait VaultContract {
         token: AccountId,
         amount: u128,
     );
+    fn fallback_mt_deposit(
+        &mut self,
+        incognito_address: String,
+        contract: AccountId,
+        token_ids: Vec<String>,
+        amounts: Vec<U128>,
+    ) -> Vec<U128>;
+    fn on_withdraw_batch(&self, results: Vec<WithdrawResult>) -> Vec<WithdrawResult>;
+    fn on_withdraw_call(&mut self, receiver: AccountId, token: AccountId, amount: U128) -> bool;
+    fn on_claim_refund(&mut self, receiver: AccountId, token: String, amount: U128);
+    fn on_relayer_fee(&mut self, relayer: AccountId, token: AccountId, fee: U128);
+    fn on_withdraw_transfer(&mut self, receiver: AccountId, token: String, amount: U128) -> bool;
+    fn on_claim_fees(&mut self, token: String, amount: U128);
+    fn on_wrap_deposit(&mut self, incognito_address: String, sender: AccountId, wrap_near: AccountId, amount: U128);
+    fn on_unwrap_near(&mut self, receiver: AccountId, token: AccountId, amount: U128) -> bool;
+    fn on_withdraw_nft(&mut self, tx_id: [u8; 32], collection: AccountId, token_id: String, index: u64) -> bool;
+    fn on_proof_of_reserves(&self, tokens: Vec<AccountId>) -> Vec<reserves::ReserveReport>;
+    fn on_recover_surplus(&mut self, token: AccountId, receiver: AccountId, owed: U128) -> PromiseOrValue<U128>;
+    fn on_surplus_recovered(&mut self, token: AccountId, amount: U128) -> U128;
 }
 
 #[near_bindgen]
 impl Vault {
     /// Initializes the beacon list
+    ///
+    /// beacons are hex encoded 64 bytes public keys or 20 bytes addresses
     #[init]
     pub fn new(
         beacons: Vec<String>,
@@ -104,12 +245,37 @@ impl Vault {
         let mut this = Self {
             tx_burn: LookupMap::new(StorageKey::Transaction), 
             beacons: TreeMap::new(StorageKey::BeaconHeight),
+            bridge_committees: TreeMap::new(StorageKey::BridgeHeight),
+            verification_policy: VerificationPolicy::Beacon,
+            quorum: Quorum::default(),
+            verified_roots: RootCache::new(),
             total_credit_amount: LookupMap::new(StorageKey::TokenAccountID),
             credit_amount: LookupMap::new(StorageKey::TokenUserAccountID),
             token_decimals: LookupMap::new(StorageKey::TokenDecimals),
+            claimable: LookupMap::new(StorageKey::Claimable),
+            token_fees: LookupMap::new(StorageKey::TokenFee),
+            accrued_fees: LookupMap::new(StorageKey::AccruedFee),
+            treasury: env::predecessor_account_id(),
+            wrap_near: None,
+            unwrap_near_receivers: LookupSet::new(StorageKey::UnwrapNear),
+            nft_collections: LookupSet::new(StorageKey::NftCollection),
+            locked_nfts: LookupSet::new(StorageKey::LockedNft),
+            deposit_caps: LookupMap::new(StorageKey::DepositCap),
+            locked_balances: LookupMap::new(StorageKey::LockedBalance),
+            burn_history: Vector::new(StorageKey::BurnHistory),
+            storage_usage: storage::StorageUsage::default(),
+            pending_deposits: LookupMap::new(StorageKey::PendingDeposit),
+            pending_withdrawals: LookupMap::new(StorageKey::PendingWithdrawal),
+            ledger_tokens: None,
+            recovering: LookupSet::new(StorageKey::Recovering),
+            shard_ids: LookupMap::new(StorageKey::ShardIds),
+            chain_id: String::new(),
+            legacy_inst_sunset: None,
+            admin: env::predecessor_account_id(),
+            committee_retention: DEFAULT_COMMITTEE_RETENTION,
         };
         // insert beacon height and list in tree
-        this.beacons.insert(&height, &beacons);
+        this.beacons.insert(&height, &Committee::from_hex(&beacons));
 
         this
     }
@@ -117,121 +283,114 @@ impl Vault {
     /// shield native token
     ///
     /// receive token from users and generate proof
-    /// validate proof on Incognito side and mint corresponding token
+    /// validate proof on Incognito side and mint corresponding token,
+    /// shielded as wNEAR once wrapped when a wNEAR contract is set
     #[payable]
     pub fn deposit(
         &mut self,
         incognito_address: String,
-    ) {
-        let total_native = env::account_balance();
-        if total_native.checked_div(1e15 as u128).unwrap_or_default().cmp(&(u64::MAX as u128)) == Ordering::Greater {
-            panic!("{}", VALUE_EXCEEDED);
+    ) -> PromiseOrValue<()> {
+        if let Some(wrap_near) = self.wrap_near.clone() {
+            return self.wrap_deposit(wrap_near, incognito_address).into();
         }
+        let total_native = env::account_balance();
+        self.assert_deposit_cap(NEAR_ADDRESS, total_native / 1e15 as u128);
 
         // extract near amount from deposit transaction
-        let amount = env::attached_deposit().checked_div(1e15 as u128).unwrap_or(0);
+        let fee = self.take_fee(NEAR_ADDRESS, env::attached_deposit(), true);
+        self.lock(NEAR_ADDRESS, env::attached_deposit() - fee);
+        let amount = (env::attached_deposit() - fee).checked_div(1e15 as u128).unwrap_or(0);
         env::log_str(format!(
             "{} {} {}",
-            incognito_address, NEAR_ADDRESS.to_string(), amount
+            incognito_address, NEAR_ADDRESS, amount
         ).as_str());
+
+        PromiseOrValue::Value(())
     }
 
     /// withdraw tokens
     ///
-    /// submit burn proof to receive token
+    /// submit burn proof to receive token, `bridge_info` carries the bridge committee
+    /// proof of the same instruction when the verification policy requires it.
+    /// the attached deposit pays the storage used, the rest is refunded
+    #[payable]
     pub fn withdraw(
         &mut self,
-        unshield_info: InteractRequest
+        unshield_info: InteractRequest,
+        bridge_info: Option<InteractRequest>,
     ) -> Promise {
-        let beacons = self.get_beacons(unshield_info.height);
-
-        // verify instruction
-        verify_inst(&unshield_info, beacons);
+        let storage_before = env::storage_usage();
+        let (_, transfer) = self.try_withdraw(&unshield_info, &bridge_info).unwrap_or_else(|err| panic!("{}", err));
+        self.charge_storage(Subsystem::Burns, storage_before);
+        transfer
+    }
 
-        // parse instruction
-        let inst = hex::decode(unshield_info.inst).unwrap_or_default();
-        let inst_ = array_ref![inst, 0, WITHDRAW_INST_LEN];
-        #[allow(clippy::ptr_offset_with_cast)]
-        let (meta_type, shard_id, token_len, token, receiver_len, receiver_key, _, unshield_amount, tx_id) =
-            array_refs![inst_, 1, 1, 1, 64, 1, 64, 24, 8, 32];
-        let meta_type = u8::from_be_bytes(*meta_type);
-        let shard_id = u8::from_be_bytes(*shard_id);
-        let mut unshield_amount = u128::from(u64::from_be_bytes(*unshield_amount));
-        let token_len = u8::from_be_bytes(*token_len);
-        let receiver_len = u8::from_be_bytes(*receiver_len);
-        let token = &token[64 - token_len as usize..];
-        let token: String = String::from_utf8(token.to_vec()).unwrap_or_default();
-        let receiver_key = &receiver_key[64 - receiver_len as usize..];
-        let receiver_key: String = String::from_utf8(receiver_key.to_vec()).unwrap_or_default();
-
-        // validate metatype and key provided
-        if (meta_type != WITHDRAW_METADATA) || shard_id != 1 {
-            panic!("{}", INVALID_METADATA);
-        }
+    /// withdraw batch
+    ///
+    /// submit several burn proofs at once, signatures of a block root are verified once for
+    /// all its proofs. a failing proof or transfer is reported in its result without
+    /// aborting the others, a tx id repeated in the batch fails as already used.
+    /// the attached deposit pays the storage used by all accepted proofs
+    #[payable]
+    pub fn withdraw_batch(
+        &mut self,
+        unshield_infos: Vec<InteractRequest>,
+        bridge_infos: Option<Vec<InteractRequest>>,
+    ) -> PromiseOrValue<Vec<WithdrawResult>> {
+        let bridge_infos: Vec<Option<InteractRequest>> = match bridge_infos {
+            Some(bridge_infos) => {
+                assert_eq!(bridge_infos.len(), unshield_infos.len(), "{}", BATCH_PROOF_MISMATCH);
+                bridge_infos.into_iter().map(Some).collect()
+            }
+            None => vec![None; unshield_infos.len()],
+        };
 
-        // check tx burn used
-        if self.tx_burn.get(&tx_id).unwrap_or_default() {
-            panic!("{}", INVALID_TX_BURN);
+        let storage_before = env::storage_usage();
+        let mut results = vec![];
+        let mut transfers: Option<Promise> = None;
+        for (index, (unshield_info, bridge_info)) in unshield_infos.iter().zip(bridge_infos.iter()).enumerate() {
+            let mut result = WithdrawResult { index: index as u32, tx_id: None, error: None };
+            match self.try_withdraw(unshield_info, bridge_info) {
+                Ok((tx_id, transfer)) => {
+                    result.tx_id = Some(hex::encode(tx_id));
+                    transfers = Some(match transfers {
+                        Some(transfers) => transfers.and(transfer),
+                        None => transfer,
+                    });
+                }
+                Err(err) => result.error = Some(err.to_string()),
+            }
+            results.push(result);
         }
-        self.tx_burn.insert(&tx_id, &true);
+        self.charge_storage(Subsystem::Burns, storage_before);
 
-        let account: AccountId = receiver_key.try_into().unwrap();
-        if token == NEAR_ADDRESS {
-            unshield_amount = unshield_amount.checked_mul(1e15 as u128).unwrap();
-            Promise::new(account).transfer(unshield_amount)
-        } else {
-            let decimals = self.token_decimals.get(&token).unwrap();
-            if decimals > 9 {
-                unshield_amount = unshield_amount.checked_mul(u128::pow(10, decimals as u32 - 9)).unwrap()
-            }
-            let token: AccountId = token.try_into().unwrap();
-            ext_ft::ft_transfer(
-                account,
-                U128(unshield_amount),
-                None,
-                token,
-                1,
-                Gas(5_000_000_000_000),
-            ).into()
+        match transfers {
+            Some(transfers) => transfers.then(ext_self::ext(env::current_account_id())
+                .with_static_gas(Gas(10_000_000_000_000))
+                .on_withdraw_batch(
+                    results,
+                )).into(),
+            None => PromiseOrValue::Value(results),
         }
     }
 
     /// swap beacon committee
     ///
     /// verify old beacon committee's signature and update new beacon committee
+    #[payable]
     pub fn swap_beacon_committee(
         &mut self,
-        swap_info: InteractRequest
+        swap_info: InteractRequest,
+        bridge_info: Option<InteractRequest>,
     ) -> bool {
-        let beacons = self.get_beacons(swap_info.height);
-
+        // the swap and roots cached by verifying it are paid by the caller's deposit
+        let storage_before = env::storage_usage();
         // verify instruction
-        verify_inst(&swap_info, beacons);
+        self.verify_committees(&swap_info, &bridge_info);
 
         // parse instruction
         let inst = hex::decode(swap_info.inst).unwrap_or_default();
-        let inst_ = array_ref![inst, 0, SWAP_COMMITTEE_INST_LEN];
-        #[allow(clippy::ptr_offset_with_cast)]
-        let (meta_type, shard_id, _, prev_height, _, height, _, num_vals) =
-            array_refs![inst_, 1, 1, 16, 16, 16, 16, 16, 16];
-        let meta_type = u8::from_be_bytes(*meta_type);
-        let shard_id = u8::from_be_bytes(*shard_id);
-        let prev_height = u128::from_be_bytes(*prev_height);
-        let height = u128::from_be_bytes(*height);
-        let num_vals = u128::from_be_bytes(*num_vals);
-
-        let mut beacons: Vec<String> = vec![];
-        for i in 0..num_vals {
-            let index = i as usize;
-            let beacon_key = array_ref![inst, SWAP_COMMITTEE_INST_LEN + index * 32, 32];
-            let beacon = hex::encode(beacon_key);
-            beacons.push(beacon);
-        }
-
-        // validate metatype and key provided
-        if meta_type != SWAP_BEACON_METADATA || shard_id != 1 {
-            panic!("{}", INVALID_METADATA);
-        }
+        let (prev_height, height, beacons) = parse_swap_inst(&inst, SWAP_BEACON_METADATA, &self.get_shard_ids(SWAP_BEACON_METADATA));
 
         let my_latest_commitee_height = self.beacons.max().unwrap_or_default();
         assert!(prev_height.eq(&my_latest_commitee_height), "{}", PREV_COMMITTEE_HEIGHT_MISMATCH);
@@ -239,6 +398,36 @@ impl Vault {
 
         // swap committee
         self.beacons.insert(&height, &beacons);
+        self.charge_storage(Subsystem::Committees, storage_before);
+
+        true
+    }
+
+    /// swap bridge committee
+    ///
+    /// verify instruction signed under the current policy and update new bridge committee
+    #[payable]
+    pub fn swap_bridge_committee(
+        &mut self,
+        swap_info: InteractRequest,
+        bridge_info: Option<InteractRequest>,
+    ) -> bool {
+        // the swap and roots cached by verifying it are paid by the caller's deposit
+        let storage_before = env::storage_usage();
+        // verify instruction
+        self.verify_committees(&swap_info, &bridge_info);
+
+        // parse instruction
+        let inst = hex::decode(swap_info.inst).unwrap_or_default();
+        let (prev_height, height, bridges) = parse_swap_inst(&inst, SWAP_BRIDGE_METADATA, &self.get_shard_ids(SWAP_BRIDGE_METADATA));
+
+        let my_latest_commitee_height = self.bridge_committees.max().unwrap_or_default();
+        assert!(prev_height.eq(&my_latest_commitee_height), "{}", PREV_COMMITTEE_HEIGHT_MISMATCH);
+        assert!(height > my_latest_commitee_height, "{}", COMMITTEE_HEIGHT_MISMATCH);
+
+        // swap committee
+        self.bridge_committees.insert(&height, &bridges);
+        self.charge_storage(Subsystem::Committees, storage_before);
 
         true
     }
@@ -246,34 +435,24 @@ impl Vault {
 
     // submit burn proof
     //
-    // prepare fund to call contract
+    // prepare fund to call contract, the attached deposit pays the storage used
+    #[payable]
     pub fn submit_burn_proof(
         &mut self,
-        burn_info: InteractRequest
+        burn_info: InteractRequest,
+        bridge_info: Option<InteractRequest>,
     ) {
-        let beacons = self.get_beacons(burn_info.height);
-
+        let storage_before = env::storage_usage();
         // verify instruction
-        verify_inst(&burn_info, beacons);
+        self.verify_committees(&burn_info, &bridge_info);
 
         // parse instruction
-        let inst = hex::decode(burn_info.inst).unwrap_or_default();
-        let inst_ = array_ref![inst, 0, WITHDRAW_INST_LEN];
-        #[allow(clippy::ptr_offset_with_cast)]
-        let (meta_type, shard_id, token_len, token, receiver_len, receiver_key, _, burn_amount, tx_id) =
-            array_refs![inst_, 1, 1, 1, 64, 1, 64, 24, 8, 32];
-        let meta_type = u8::from_be_bytes(*meta_type);
-        let shard_id = u8::from_be_bytes(*shard_id);
-        let burn_amount = u128::from(u64::from_be_bytes(*burn_amount));
-        let token_len = u8::from_be_bytes(*token_len);
-        let receiver_len = u8::from_be_bytes(*receiver_len);
-        let token = &token[64 - token_len as usize..];
-        let token: String = String::from_utf8(token.to_vec()).unwrap_or_default();
-        let receiver_key = &receiver_key[64 - receiver_len as usize..];
-        let receiver_key: String = String::from_utf8(receiver_key.to_vec()).unwrap_or_default();
-
-        // validate metatype and key provided
-        if (meta_type != BURN_METADATA) || shard_id != 1 {
+        let inst = self.strip_domain(hex::decode(burn_info.inst).unwrap_or_default()).unwrap_or_else(|err| panic!("{}", err));
+        let decoded = self.decode_withdraw_inst(&inst).unwrap_or_else(|err| panic!("{}", err));
+        let WithdrawInst { meta_type, token, receiver, amount: burn_amount, tx_id, .. } = decoded.clone();
+
+        // validate metatype
+        if meta_type != BURN_METADATA {
             panic!("{}", INVALID_METADATA);
         }
 
@@ -282,81 +461,792 @@ impl Vault {
             panic!("{}", INVALID_TX_BURN);
         }
         self.tx_burn.insert(&tx_id, &true);
+        self.record_burn(&decoded);
 
-        let token: AccountId = AccountId::try_from(hex::encode(token)).unwrap();
-        let account: AccountId = AccountId::try_from(hex::encode(receiver_key)).unwrap();
+        // named or implicit account ids, carried as utf8 like in withdraw instructions
+        let token: AccountId = token.try_into().expect(INVALID_METADATA);
+        let account: AccountId = receiver.try_into().expect(INVALID_RECEIVER);
 
         let amount = self.total_credit_amount.get(&token.to_string()).unwrap_or_default();
         self.total_credit_amount.insert(&token.to_string(), &(amount + burn_amount));
+        // credits are owed through `total_credit_amount` from now on
+        self.unlock(token.as_str(), burn_amount.saturating_mul(self.credit_unit(token.as_str())))
+            .unwrap_or_else(|err| panic!("{}", err));
         let amount = self.credit_amount.get(&(token.to_string(), account.to_string())).unwrap_or_default();
         self.credit_amount.insert(&(token.to_string(), account.to_string()), &(amount + burn_amount));
+        self.charge_storage(Subsystem::Burns, storage_before);
     }
 
 
 
-    /// getters
+    // getters
 
     /// get beacon list by height
     pub fn get_beacons(&self, height: u128) -> Vec<String> {
-        let get_height_key = self.beacons.lower(&(height + 1)).unwrap();
-        self.beacons.get(&get_height_key).unwrap()
+        self.get_committee(height).to_hex()
     }
 
     /// check tx burn used
-    pub fn get_tx_burn_used(self, tx_id: &[u8; 32]) -> bool {
+    pub fn get_tx_burn_used(&self, tx_id: &[u8; 32]) -> bool {
         self.tx_burn.get(tx_id).unwrap_or_default()
     }
 
+    /// check burn of `tx_id` was processed, hex or base58 encoded
+    pub fn is_burn_processed(&self, tx_id: String) -> bool {
+        let tx_id = parse_tx_id(&tx_id).expect(INVALID_TX_ID);
+        self.tx_burn.get(&tx_id).unwrap_or_default()
+    }
+
+    /// check burns of up to `MAX_BURN_QUERY` tx ids were processed, in the order given,
+    /// none for ids that are not valid hex or base58
+    pub fn are_burns_processed(&self, tx_ids: Vec<String>) -> Vec<Option<bool>> {
+        assert!(tx_ids.len() <= MAX_BURN_QUERY, "{}", TOO_MANY_TX_IDS);
+        tx_ids.iter()
+            .map(|tx_id| parse_tx_id(tx_id).map(|tx_id| self.tx_burn.get(&tx_id).unwrap_or_default()))
+            .collect()
+    }
+
+    /// get admin account
+    pub fn get_admin(&self) -> AccountId {
+        self.admin.clone()
+    }
+
+    /// get refund of withdraw calls claimable by account, multi tokens as `contract:token_id`
+    pub fn get_claimable(&self, account_id: AccountId, token: String) -> U128 {
+        U128(self.claimable.get(&(token, account_id.to_string())).unwrap_or_default())
+    }
+
+    /// get highest vault balance of token shields are accepted up to, in Incognito's 9 decimals
+    pub fn get_deposit_cap(&self, token: String) -> U128 {
+        U128(self.deposit_caps.get(&token).unwrap_or(DEFAULT_DEPOSIT_CAP))
+    }
+
+    // setters
+
+    /// hand vault settings over to another account
+    pub fn set_admin(&mut self, admin: AccountId) {
+        self.assert_admin();
+        self.admin = admin;
+    }
+
+    /// set deposit cap of token, `NEAR_ADDRESS` for native NEAR, none to restore the default
+    pub fn set_deposit_cap(&mut self, token: String, cap: Option<U128>) {
+        self.assert_admin();
+        match cap {
+            Some(cap) => self.deposit_caps.insert(&token, &cap.0),
+            None => self.deposit_caps.remove(&token),
+        };
+    }
+
+    /// claim refund
+    ///
+    /// transfer tokens a receiver contract refunded from withdraw calls or failed payouts
+    /// to the caller, multi tokens are claimed as `contract:token_id`
+    pub fn claim_refund(&mut self, token: String) -> Promise {
+        let receiver = env::predecessor_account_id();
+        let key = (token.clone(), receiver.to_string());
+        let amount = self.claimable.remove(&key).unwrap_or_default();
+        assert!(amount > 0, "{}", NOTHING_TO_CLAIM);
+        self.unlock(&token, amount).unwrap_or_else(|err| panic!("{}", err));
+        self.add_withdrawing(&token, amount);
+
+        let transfer = match split_mt_token_key(&token) {
+            Some((contract, token_id)) => ext_mt::ext(AccountId::try_from(contract.to_string()).expect(INVALID_METADATA))
+                .with_attached_deposit(1)
+                .with_static_gas(Gas(10_000_000_000_000))
+                .mt_transfer(
+                    receiver.clone(),
+                    token_id.to_string(),
+                    U128(amount),
+                    None,
+                    None,
+                ),
+            None => ext_ft::ext(AccountId::try_from(token.clone()).expect(INVALID_METADATA))
+                .with_attached_deposit(1)
+                .with_static_gas(Gas(5_000_000_000_000))
+                .ft_transfer(
+                    receiver.clone(),
+                    U128(amount),
+                    None,
+                ),
+        };
+        transfer
+            .then(ext_self::ext(env::current_account_id())
+                .with_static_gas(Gas(5_000_000_000_000))
+                .on_claim_refund(
+                    receiver,
+                    token,
+                    U128(amount),
+                ))
+    }
+
     /// fallbacks
+    ///
+    /// emit the shield of received tokens, the whole amount is returned unused when the
+    /// token metadata or vault balance cannot be read or the deposit cap is exceeded
+    #[private]
     pub fn fallback_deposit(&mut self, incognito_address: String, token: AccountId, amount: u128) -> PromiseOrValue<U128> {
+        // also checked here as `#[private]` only guards the wasm entry point
+        assert_eq!(env::predecessor_account_id(), env::current_account_id(), "{}", NOT_VAULT_CALLBACK);
         assert_eq!(env::promise_results_count(), 2, "This is a callback method");
+        // cleared before anything can fail, a panic would keep it pending while the tokens are refunded
+        self.remove_pending(token.as_str(), amount);
 
         // handle the result from the second cross contract call this method is a callback for
-        let token_meta_data: FungibleTokenMetadata = match env::promise_result(0) {
-            PromiseResult::NotReady => unreachable!(),
-            PromiseResult::Failed => panic!("{:?}", b"Unable to make comparison"),
-            PromiseResult::Successful(result) => near_sdk::serde_json::from_slice::<FungibleTokenMetadata>(&result)
-                .unwrap()
-                .into(),
+        let token_meta_data = match env::promise_result(0) {
+            PromiseResult::Successful(result) => near_sdk::serde_json::from_slice::<FungibleTokenMetadata>(&result).ok(),
+            _ => None,
         };
 
         // handle the result from the first cross contract call this method is a callback for
-        let mut vault_acc_balance: u128 = match env::promise_result(1) {
-            PromiseResult::NotReady => unreachable!(),
-            PromiseResult::Failed => panic!("{:?}", b"Unable to make comparison"),
-            PromiseResult::Successful(result) => near_sdk::serde_json::from_slice::<U128>(&result)
-                .unwrap()
-                .into(),
+        let vault_acc_balance = match env::promise_result(1) {
+            PromiseResult::Successful(result) => near_sdk::serde_json::from_slice::<U128>(&result).ok(),
+            _ => None,
         };
 
-        let mut emit_amount = amount;
-        if token_meta_data.decimals > 9 {
-            emit_amount = amount.checked_div(u128::pow(10, (token_meta_data.decimals - 9) as u32)).unwrap_or_default();
-            vault_acc_balance = vault_acc_balance.checked_div(u128::pow(10, (token_meta_data.decimals - 9) as u32)).unwrap_or_default();
-        }
-
-        if vault_acc_balance.cmp(&(u64::MAX as u128)) == Ordering::Greater {
-            panic!("{}", VALUE_EXCEEDED)
+        let (token_meta_data, vault_acc_balance) = match (token_meta_data, vault_acc_balance) {
+            (Some(token_meta_data), Some(vault_acc_balance)) => (token_meta_data, vault_acc_balance.0),
+            _ => return PromiseOrValue::Value(U128(self.refund_deposit(token.as_str(), amount, TOKEN_BALANCE_UNAVAILABLE))),
+        };
+        if !self.within_deposit_cap(token.as_str(), vault_acc_balance / incognito_unit(token_meta_data.decimals)) {
+            return PromiseOrValue::Value(U128(self.refund_deposit(token.as_str(), amount, VALUE_EXCEEDED)));
         }
 
         let decimals_stored = self.token_decimals.get(&token.to_string()).unwrap_or_default();
         if decimals_stored == 0 {
             self.token_decimals.insert(&token.to_string(), &token_meta_data.decimals);
         }
-
-        env::log_str(
-            format!(
-                "{} {} {}",
-                incognito_address, token, emit_amount
-            ).as_str());
+        self.emit_shield(&incognito_address, token.as_str(), amount, token_meta_data.decimals);
 
         PromiseOrValue::Value(U128(0))
     }
+
+    /// record the part of a withdraw call the receiver did not use as claimable,
+    /// everything is refunded when the transfer itself failed. returns whether the
+    /// receiver used any of it
+    #[private]
+    pub fn on_withdraw_call(&mut self, receiver: AccountId, token: AccountId, amount: U128) -> bool {
+        self.remove_withdrawing(token.as_str(), amount.0);
+        let used = match env::promise_result(0) {
+            PromiseResult::Successful(result) => near_sdk::serde_json::from_slice::<U128>(&result)
+                .map(|used| used.0.min(amount.0))
+                .unwrap_or(amount.0),
+            _ => 0,
+        };
+        let refund = amount.0 - used;
+        if refund > 0 {
+            self.add_claimable(&receiver, token.as_str(), refund);
+            env::log_str(format!("{} refund {} {} claimable", receiver, refund, token).as_str());
+        }
+
+        used > 0
+    }
+
+    /// restore the claim when its transfer failed
+    #[private]
+    pub fn on_claim_refund(&mut self, receiver: AccountId, token: String, amount: U128) {
+        self.remove_withdrawing(&token, amount.0);
+        if !promise_succeeded(0) {
+            self.add_claimable(&receiver, &token, amount.0);
+        }
+    }
+
+    /// make a relayer fee whose transfer failed claimable with `claim_refund`
+    #[private]
+    pub fn on_relayer_fee(&mut self, relayer: AccountId, token: AccountId, fee: U128) {
+        self.remove_withdrawing(token.as_str(), fee.0);
+        if !promise_succeeded(0) {
+            self.add_claimable(&relayer, token.as_str(), fee.0);
+            env::log_str(format!("{} relayer fee {} {} claimable", relayer, fee.0, token).as_str());
+        }
+    }
+
+    /// settle a token payout, a failed one is made claimable with `claim_refund`.
+    /// returns whether the payout was delivered
+    #[private]
+    pub fn on_withdraw_transfer(&mut self, receiver: AccountId, token: String, amount: U128) -> bool {
+        self.remove_withdrawing(&token, amount.0);
+        if promise_succeeded(0) {
+            return true;
+        }
+        self.add_claimable(&receiver, &token, amount.0);
+        env::log_str(format!("{} unshield {} {} claimable", receiver, amount.0, token).as_str());
+
+        false
+    }
+
+    /// report transfers of `withdraw_batch`, one promise result per accepted proof
+    #[private]
+    pub fn on_withdraw_batch(&self, results: Vec<WithdrawResult>) -> Vec<WithdrawResult> {
+        let mut results = results;
+        let accepted = results.iter_mut().filter(|result| result.error.is_none());
+        for (i, result) in accepted.enumerate() {
+            // token payouts end in a callback returning false when they did not arrive
+            let delivered = matches!(env::promise_result(i as u64), PromiseResult::Successful(result) if result != b"false");
+            if !delivered {
+                result.error = Some(TRANSFER_FAILED.to_string());
+            }
+        }
+
+        results
+    }
+}
+
+impl Vault {
+    /// committee active at beacon height
+    pub(crate) fn get_committee(&self, height: u128) -> Committee {
+        self.find_committee(height).expect(BEACON_HEIGHT_NOT_FOUND)
+    }
+
+    pub(crate) fn find_committee(&self, height: u128) -> Option<Committee> {
+        let get_height_key = self.beacons.lower(&(height + 1))?;
+        self.beacons.get(&get_height_key)
+    }
+
+    /// verify and parse unshield proof, mark its burn used and build the transfer
+    ///
+    /// nothing is written when an error is returned
+    pub(crate) fn try_withdraw(
+        &mut self,
+        unshield_info: &InteractRequest,
+        bridge_info: &Option<InteractRequest>,
+    ) -> Result<([u8; 32], Promise), &'static str> {
+        // verify instruction
+        let verified = self.try_verify_committees(unshield_info, bridge_info)?;
+
+        // parse instruction
+        let inst = self.strip_domain(hex::decode(&unshield_info.inst).unwrap_or_default())?;
+        let decoded = self.decode_withdraw_inst(&inst)?;
+        let WithdrawInst { meta_type, token, receiver: receiver_key, amount: mut unshield_amount, tx_id, payload, relayer_fee, .. } =
+            decoded.clone();
+
+        // validate metatype
+        let (msg, memo, token_id) = match meta_type {
+            WITHDRAW_METADATA | WITHDRAW_FEE_METADATA | WITHDRAW_WIDE_METADATA => (None, None, None),
+            WITHDRAW_CALL_METADATA => (payload, None, None),
+            WITHDRAW_MEMO_METADATA => (None, payload, None),
+            MT_WITHDRAW_METADATA => (None, None, payload),
+            _ => return Err(INVALID_METADATA),
+        };
+        if relayer_fee > unshield_amount {
+            return Err(INVALID_RELAYER_FEE);
+        }
+
+        // check tx burn used
+        if self.tx_burn.get(&tx_id).unwrap_or_default() {
+            return Err(INVALID_TX_BURN);
+        }
+
+        let account: AccountId = receiver_key.try_into().map_err(|_| INVALID_RECEIVER)?;
+        // multi tokens are registered per token id
+        let token_key = match &token_id {
+            Some(token_id) => mt_token_key(&token, token_id),
+            None => token.clone(),
+        };
+        // none for native NEAR
+        let (token, unit) = if token == NEAR_ADDRESS {
+            if msg.is_some() {
+                return Err(NATIVE_TRANSFER_CALL);
+            }
+            if token_id.is_some() {
+                return Err(INVALID_METADATA);
+            }
+            (None, 1e15 as u128)
+        } else {
+            let decimals = self.token_decimals.get(&token_key).ok_or(UNKNOWN_TOKEN)?;
+            (Some(AccountId::try_from(token).map_err(|_| INVALID_METADATA)?), incognito_unit(decimals))
+        };
+        unshield_amount = unshield_amount.checked_mul(unit).ok_or(AMOUNT_OVERFLOW)?;
+        let fee = relayer_fee.checked_mul(unit).ok_or(AMOUNT_OVERFLOW)?;
+        let fee_token = if token.is_some() { token_key } else { NEAR_ADDRESS.to_string() };
+        let protocol_fee = self.protocol_fee(&fee_token, unshield_amount, false);
+        let net_amount = unshield_amount.checked_sub(fee).and_then(|amount| amount.checked_sub(protocol_fee)).ok_or(INVALID_RELAYER_FEE)?;
+        // the first write, so a payout above the ledger writes nothing
+        self.unlock(&fee_token, unshield_amount - protocol_fee)?;
+
+        // pay the relayer first so the payout stays the result reported to callers
+        let fee_transfer = if fee > 0 {
+            let relayer = env::predecessor_account_id();
+            env::log_str(format!("relayer fee {} {} {}", hex::encode(tx_id), relayer, fee).as_str());
+            env::log_str(format!("unshield {} {} {}", hex::encode(tx_id), account, net_amount).as_str());
+            Some(match &token {
+                // the caller account exists, a native transfer to it does not fail
+                None => Promise::new(relayer).transfer(fee),
+                Some(token) => ext_ft::ext(token.clone())
+                    .with_attached_deposit(1)
+                    .with_static_gas(Gas(5_000_000_000_000))
+                    .ft_transfer(
+                        relayer.clone(),
+                        U128(fee),
+                        None,
+                    )
+                    .then(ext_self::ext(env::current_account_id())
+                        .with_static_gas(Gas(5_000_000_000_000))
+                        .on_relayer_fee(
+                            relayer,
+                            token.clone(),
+                            U128(fee),
+                        )),
+            })
+        } else {
+            None
+        };
+
+        let transfer = match (&token, msg) {
+            (Some(token), None) if token_id.is_some() => then_after(fee_transfer, ext_mt::ext(token.clone())
+                .with_attached_deposit(1)
+                .with_static_gas(Gas(10_000_000_000_000))
+                .mt_transfer(
+                    account.clone(),
+                    token_id.unwrap_or_default(),
+                    U128(net_amount),
+                    None,
+                    memo,
+                ))
+                .then(ext_self::ext(env::current_account_id())
+                    .with_static_gas(Gas(10_000_000_000_000))
+                    .on_withdraw_transfer(
+                        account.clone(),
+                        fee_token.clone(),
+                        U128(net_amount),
+                    )),
+            (None, _) => {
+                // native transfers have no memo, exchanges read it from the unshield event
+                if let Some(memo) = &memo {
+                    env::log_str(format!("unshield {} {} {} {}", hex::encode(tx_id), account, net_amount, memo).as_str());
+                }
+                then_after(fee_transfer, Promise::new(account.clone()).transfer(net_amount))
+            }
+            (Some(token), None) if self.is_unwrapped(token, &account) => {
+                self.unwrap_transfer(fee_transfer, token.clone(), account.clone(), net_amount)
+            }
+            (Some(token), None) => then_after(fee_transfer, ext_ft::ext(token.clone())
+                .with_attached_deposit(1)
+                .with_static_gas(Gas(5_000_000_000_000))
+                .ft_transfer(
+                    account.clone(),
+                    U128(net_amount),
+                    memo,
+                ))
+                .then(ext_self::ext(env::current_account_id())
+                    .with_static_gas(Gas(10_000_000_000_000))
+                    .on_withdraw_transfer(
+                        account.clone(),
+                        fee_token.clone(),
+                        U128(net_amount),
+                    )),
+            (Some(token), Some(msg)) => then_after(fee_transfer, ext_ft::ext(token.clone())
+                .with_attached_deposit(1)
+                .with_static_gas(Gas(50_000_000_000_000))
+                .ft_transfer_call(
+                    account.clone(),
+                    U128(net_amount),
+                    None,
+                    msg,
+                ))
+                .then(ext_self::ext(env::current_account_id())
+                    .with_static_gas(Gas(10_000_000_000_000))
+                    .on_withdraw_call(
+                        account.clone(),
+                        token.clone(),
+                        U128(net_amount),
+                    )),
+        };
+        // token payouts stay owed until their callback settles them
+        if let Some(token) = &token {
+            if fee > 0 {
+                self.add_withdrawing(token.as_str(), fee);
+            }
+            self.add_withdrawing(&fee_token, net_amount);
+        }
+        self.cache_roots(&verified);
+        if protocol_fee > 0 {
+            self.accrue_fee(&fee_token, protocol_fee);
+            env::log_str(format!("protocol fee {} {}", hex::encode(tx_id), protocol_fee).as_str());
+        }
+        self.tx_burn.insert(&tx_id, &true);
+        self.record_burn(&decoded);
+
+        Ok((tx_id, transfer))
+    }
+
+    /// take the shield fee of `amount` and emit the shield event in Incognito's 9 decimals
+    pub(crate) fn emit_shield(&mut self, incognito_address: &str, token: &str, amount: u128, decimals: u8) {
+        let fee = self.take_fee(token, amount, true);
+        self.lock(token, amount - fee);
+        let emit_amount = (amount - fee) / incognito_unit(decimals);
+        env::log_str(format!(
+            "{} {} {}",
+            incognito_address, token, emit_amount
+        ).as_str());
+    }
+
+    pub(crate) fn add_claimable(&mut self, receiver: &AccountId, token: &str, amount: u128) {
+        let key = (token.to_string(), receiver.to_string());
+        let claimable = self.claimable.get(&key).unwrap_or_default();
+        self.claimable.insert(&key, &(claimable + amount));
+        self.lock(token, amount);
+    }
+
+    /// check vault balance of token, scaled to Incognito's 9 decimals, is within its deposit cap
+    pub(crate) fn assert_deposit_cap(&self, token: &str, balance: u128) {
+        assert!(self.within_deposit_cap(token, balance), "{}", VALUE_EXCEEDED);
+    }
+
+    pub(crate) fn within_deposit_cap(&self, token: &str, balance: u128) -> bool {
+        balance <= self.deposit_caps.get(&token.to_string()).unwrap_or(DEFAULT_DEPOSIT_CAP)
+    }
+
+    /// log a received deposit given back to its sender, returns the amount to report unused
+    pub(crate) fn refund_deposit(&self, token: &str, amount: u128, reason: &str) -> u128 {
+        env::log_str(format!("refund {} {}: {}", token, amount, reason).as_str());
+        amount
+    }
+
+    pub(crate) fn assert_admin(&self) {
+        assert_eq!(env::predecessor_account_id(), self.admin, "{}", NOT_ADMIN);
+    }
 }
 
 #[cfg(all(test, not(target_arch = "wasm32")))]
 mod tests {
     use super::*;
+    use crate::root_cache::root_key;
+    use crate::test_utils;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, RuntimeFeesConfig, VMConfig};
+
+    fn setup() -> Vault {
+        testing_env!(VMContextBuilder::new().build());
+        Vault::new(test_utils::beacons(), 0)
+    }
+
+    fn result(index: u32, tx_id: Option<[u8; 32]>, error: Option<&str>) -> WithdrawResult {
+        WithdrawResult { index, tx_id: tx_id.map(hex::encode), error: error.map(str::to_string) }
+    }
+
+    #[test]
+    fn test_withdraw_batch() {
+        let mut vault = setup();
+        let near = test_utils::withdraw_inst(NEAR_ADDRESS, "alice.near", 5, [1; 32]);
+        let ft = test_utils::withdraw_inst("token.near", "bob.near", 5, [2; 32]);
+        let requests = vec![
+            test_utils::shielded_request(&mut vault, &near),
+            test_utils::shielded_request(&mut vault, &near),
+            test_utils::shielded_request(&mut vault, &ft),
+        ];
+        test_utils::attach_storage_deposit();
+        assert!(matches!(vault.withdraw_batch(requests, None), PromiseOrValue::Promise(_)));
+        assert!(vault.tx_burn.get(&[1; 32]).unwrap_or_default());
+        assert!(!vault.tx_burn.get(&[2; 32]).unwrap_or_default());
+    }
+
+    #[test]
+    fn test_withdraw_batch_all_failed() {
+        let mut vault = setup();
+        let bad_receiver = test_utils::withdraw_inst(NEAR_ADDRESS, "Not An Account", 5, [1; 32]);
+        let requests = vec![
+            test_utils::signed_request(),
+            test_utils::shielded_request(&mut vault, &bad_receiver),
+        ];
+        test_utils::attach_storage_deposit();
+        match vault.withdraw_batch(requests, None) {
+            PromiseOrValue::Value(results) => assert_eq!(results, vec![
+                result(0, None, Some(INVALID_MERKLE_TREE)),
+                result(1, None, Some(INVALID_RECEIVER)),
+            ]),
+            PromiseOrValue::Promise(_) => panic!("no transfer expected"),
+        }
+        assert!(!vault.tx_burn.get(&[1; 32]).unwrap_or_default());
+    }
+
+    #[test]
+    fn test_try_withdraw_error_writes_nothing() {
+        let mut vault = setup();
+        let request = test_utils::signed_request();
+        let before = env::storage_usage();
+        // signatures are valid, the instruction is not in the block
+        assert_eq!(vault.try_withdraw(&request, &None).err(), Some(INVALID_MERKLE_TREE));
+        assert!(!vault.verified_roots.contains(&root_key(&request, false, &vault.quorum)));
+        assert_eq!(vault.verified_roots.len(), 0);
+        assert_eq!(env::storage_usage(), before);
+    }
+
+    #[test]
+    #[should_panic(expected = "Bridge proofs must pair with unshield proofs")]
+    fn test_withdraw_batch_bridge_mismatch() {
+        let mut vault = setup();
+        vault.withdraw_batch(vec![test_utils::signed_request()], Some(vec![]));
+    }
+
+    #[test]
+    fn test_withdraw_call() {
+        let mut vault = setup();
+        vault.token_decimals.insert(&"token.near".to_string(), &6);
+        let inst = test_utils::withdraw_payload_inst(WITHDRAW_CALL_METADATA, "token.near", "dapp.near", 5, [1; 32], "stake");
+        let request = test_utils::shielded_request(&mut vault, &inst);
+        test_utils::attach_storage_deposit();
+        vault.withdraw(request, None);
+        assert!(vault.tx_burn.get(&[1; 32]).unwrap_or_default());
+    }
+
+    #[test]
+    #[should_panic(expected = "Native NEAR cannot be withdrawn with a message")]
+    fn test_withdraw_call_native() {
+        let mut vault = setup();
+        let inst = test_utils::withdraw_payload_inst(WITHDRAW_CALL_METADATA, NEAR_ADDRESS, "dapp.near", 5, [1; 32], "stake");
+        let request = test_utils::shielded_request(&mut vault, &inst);
+        vault.withdraw(request, None);
+    }
+
+    #[test]
+    fn test_withdraw_memo() {
+        let mut vault = setup();
+        let inst = test_utils::withdraw_payload_inst(WITHDRAW_MEMO_METADATA, NEAR_ADDRESS, "exchange.near", 5, [1; 32], "user-42");
+        let request = test_utils::shielded_request(&mut vault, &inst);
+        test_utils::attach_storage_deposit();
+        vault.withdraw(request, None);
+        assert_eq!(
+            near_sdk::test_utils::get_logs(),
+            vec![format!("unshield {} exchange.near 5000000000000000 user-42", hex::encode([1; 32]))]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid instruction length")]
+    fn test_withdraw_memo_truncated() {
+        let mut vault = setup();
+        let mut inst = test_utils::withdraw_inst(NEAR_ADDRESS, "exchange.near", 5, [1; 32]);
+        inst[0] = WITHDRAW_MEMO_METADATA;
+        let request = test_utils::shielded_request(&mut vault, &inst);
+        vault.withdraw(request, None);
+    }
+
+    #[test]
+    fn test_withdraw_relayer_fee() {
+        let mut vault = setup();
+        vault.token_decimals.insert(&"token.near".to_string(), &12);
+        let inst = test_utils::withdraw_fee_inst("token.near", "alice.near", 5, [1; 32], 2);
+        let request = test_utils::shielded_request(&mut vault, &inst);
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(3)).build());
+        test_utils::attach_storage_deposit();
+        vault.withdraw(request, None);
+        assert_eq!(near_sdk::test_utils::get_logs(), vec![
+            format!("relayer fee {} {} 2000", hex::encode([1; 32]), accounts(3)),
+            format!("unshield {} alice.near 3000", hex::encode([1; 32])),
+        ]);
+    }
+
+    #[test]
+    fn test_deposit_protocol_fee() {
+        let mut vault = setup();
+        vault.set_token_fee(NEAR_ADDRESS.to_string(), TokenFee { shield_bps: 30, unshield_bps: 0 });
+        testing_env!(VMContextBuilder::new().attached_deposit(10u128.pow(24)).build());
+        vault.deposit("incognito-address".to_string());
+        assert_eq!(near_sdk::test_utils::get_logs(), vec![format!("incognito-address {} 997000000", NEAR_ADDRESS)]);
+        assert_eq!(vault.get_accrued_fees(NEAR_ADDRESS.to_string()), U128(3 * 10u128.pow(21)));
+    }
+
+    #[test]
+    fn test_is_burn_processed() {
+        let mut vault = setup();
+        vault.token_decimals.insert(&"token.near".to_string(), &9);
+        let inst = test_utils::withdraw_inst("token.near", "alice.near", 5, [1; 32]);
+        let request = test_utils::shielded_request(&mut vault, &inst);
+        test_utils::attach_storage_deposit();
+        vault.withdraw(request, None);
+
+        assert!(vault.is_burn_processed(hex::encode([1u8; 32])));
+        assert!(vault.is_burn_processed(bs58::encode([1u8; 32]).into_string()));
+        assert_eq!(
+            vault.are_burns_processed(vec![hex::encode([2u8; 32]), "00".to_string(), hex::encode([1u8; 32])]),
+            vec![Some(false), None, Some(true)]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Too many burn tx ids in one query")]
+    fn test_are_burns_processed_too_many() {
+        setup().are_burns_processed(vec![hex::encode([1u8; 32]); MAX_BURN_QUERY + 1]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Burn tx id must be 32 bytes in hex or base58")]
+    fn test_is_burn_processed_invalid() {
+        setup().is_burn_processed("00".to_string());
+    }
+
+    #[test]
+    fn test_submit_burn_proof() {
+        let mut vault = setup();
+        let implicit = "ab".repeat(32);
+        for (tx_id, receiver) in [([1; 32], "alice.near"), ([2; 32], implicit.as_str())] {
+            let mut inst = test_utils::withdraw_inst("token.near", receiver, 5, tx_id);
+            inst[0] = BURN_METADATA;
+            let request = test_utils::shielded_request(&mut vault, &inst);
+            test_utils::attach_storage_deposit();
+            vault.submit_burn_proof(request, None);
+            let key = ("token.near".to_string(), receiver.to_string());
+            assert_eq!(vault.credit_amount.get(&key), Some(5));
+        }
+        assert_eq!(vault.total_credit_amount.get(&"token.near".to_string()), Some(10));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid receiver account")]
+    fn test_submit_burn_proof_invalid_receiver() {
+        let mut vault = setup();
+        let mut inst = test_utils::withdraw_inst("token.near", "Not An Account", 5, [1; 32]);
+        inst[0] = BURN_METADATA;
+        let request = test_utils::shielded_request(&mut vault, &inst);
+        vault.submit_burn_proof(request, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "the total balance greater than max value allowed to shield")]
+    fn test_deposit_cap() {
+        let mut vault = setup();
+        vault.set_deposit_cap(NEAR_ADDRESS.to_string(), Some(U128(1_000_000_000)));
+        assert_eq!(vault.get_deposit_cap(NEAR_ADDRESS.to_string()), U128(1_000_000_000));
+        testing_env!(VMContextBuilder::new().account_balance(2 * 10u128.pow(24)).attached_deposit(10u128.pow(24)).build());
+        vault.deposit("incognito-address".to_string());
+    }
+
+    #[test]
+    fn test_withdraw_wide_amount() {
+        let mut vault = setup();
+        vault.token_decimals.insert(&"token.near".to_string(), &18);
+        // above u64 in Incognito's 9 decimals
+        let amount = u64::MAX as u128 * 4;
+        let amount_end = crate::inst::WIDE_WITHDRAW_LAYOUT.len() - 32;
+        let mut inst = test_utils::withdraw_inst("token.near", "alice.near", 0, [1; 32]);
+        inst[0] = WITHDRAW_WIDE_METADATA;
+        inst[amount_end - 16..amount_end].copy_from_slice(&amount.to_be_bytes());
+        let request = test_utils::shielded_request(&mut vault, &inst);
+        test_utils::attach_storage_deposit();
+        vault.withdraw(request, None);
+        assert!(vault.tx_burn.get(&[1; 32]).unwrap_or_default());
+    }
+
+    #[test]
+    fn test_withdraw_protocol_fee() {
+        let mut vault = setup();
+        vault.token_decimals.insert(&"token.near".to_string(), &9);
+        vault.set_token_fee("token.near".to_string(), TokenFee { shield_bps: 0, unshield_bps: 100 });
+        let inst = test_utils::withdraw_fee_inst("token.near", "alice.near", 1_000, [1; 32], 20);
+        let request = test_utils::shielded_request(&mut vault, &inst);
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(3)).build());
+        test_utils::attach_storage_deposit();
+        vault.withdraw(request, None);
+        assert_eq!(near_sdk::test_utils::get_logs(), vec![
+            format!("relayer fee {} {} 20", hex::encode([1; 32]), accounts(3)),
+            format!("unshield {} alice.near 970", hex::encode([1; 32])),
+            format!("protocol fee {} 10", hex::encode([1; 32])),
+        ]);
+        assert_eq!(vault.get_accrued_fees("token.near".to_string()), U128(10));
+    }
+
+    #[test]
+    #[should_panic(expected = "Relayer fee exceeds unshield amount")]
+    fn test_withdraw_relayer_fee_above_amount() {
+        let mut vault = setup();
+        let inst = test_utils::withdraw_fee_inst(NEAR_ADDRESS, "alice.near", 5, [1; 32], 6);
+        let request = test_utils::shielded_request(&mut vault, &inst);
+        vault.withdraw(request, None);
+    }
+
+    fn with_promise_result(result: PromiseResult) {
+        testing_env!(
+            VMContextBuilder::new().predecessor_account_id(accounts(1)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![result]
+        );
+    }
+
+    #[test]
+    fn test_on_withdraw_call_refund() {
+        let mut vault = setup();
+        with_promise_result(PromiseResult::Successful(b"\"3\"".to_vec()));
+        assert!(vault.on_withdraw_call(accounts(1), accounts(2), U128(5)));
+        assert_eq!(vault.get_claimable(accounts(1), accounts(2).to_string()), U128(2));
+
+        with_promise_result(PromiseResult::Failed);
+        assert!(!vault.on_withdraw_call(accounts(1), accounts(2), U128(5)));
+        assert_eq!(vault.get_claimable(accounts(1), accounts(2).to_string()), U128(7));
+    }
+
+    #[test]
+    fn test_on_relayer_fee() {
+        let mut vault = setup();
+        with_promise_result(PromiseResult::Successful(vec![]));
+        vault.on_relayer_fee(accounts(3), accounts(2), U128(2));
+        assert_eq!(vault.get_claimable(accounts(3), accounts(2).to_string()), U128(0));
+
+        // a failed fee stays owed to the relayer instead of turning into surplus
+        with_promise_result(PromiseResult::Failed);
+        vault.on_relayer_fee(accounts(3), accounts(2), U128(2));
+        assert_eq!(vault.get_claimable(accounts(3), accounts(2).to_string()), U128(2));
+        assert_eq!(vault.locked_balances.get(&accounts(2).to_string()), Some(2));
+    }
+
+    #[test]
+    fn test_claim_refund() {
+        let mut vault = setup();
+        with_promise_result(PromiseResult::Failed);
+        vault.on_withdraw_call(accounts(1), accounts(2), U128(5));
+
+        vault.claim_refund(accounts(2).to_string());
+        assert_eq!(vault.get_claimable(accounts(1), accounts(2).to_string()), U128(0));
+
+        // failed claim transfer is claimable again
+        vault.on_claim_refund(accounts(1), accounts(2).to_string(), U128(5));
+        assert_eq!(vault.get_claimable(accounts(1), accounts(2).to_string()), U128(5));
+    }
+
+    #[test]
+    #[should_panic(expected = "No refund to claim")]
+    fn test_claim_refund_empty() {
+        let mut vault = setup();
+        vault.claim_refund(accounts(2).to_string());
+    }
+
+    #[test]
+    fn test_on_withdraw_batch() {
+        let vault = setup();
+        testing_env!(
+            VMContextBuilder::new().build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(vec![]), PromiseResult::Failed]
+        );
+        let results = vault.on_withdraw_batch(vec![
+            result(0, Some([1; 32]), None),
+            result(1, None, Some(INVALID_TX_BURN)),
+            result(2, Some([2; 32]), None),
+        ]);
+        assert_eq!(results, vec![
+            result(0, Some([1; 32]), None),
+            result(1, None, Some(INVALID_TX_BURN)),
+            result(2, Some([2; 32]), Some(TRANSFER_FAILED)),
+        ]);
+    }
+
+    #[test]
+    fn test_on_withdraw_batch_undelivered() {
+        let vault = setup();
+        testing_env!(
+            VMContextBuilder::new().build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(b"true".to_vec()), PromiseResult::Successful(b"false".to_vec())]
+        );
+        let results = vault.on_withdraw_batch(vec![result(0, Some([1; 32]), None), result(1, Some([2; 32]), None)]);
+        assert_eq!(results, vec![result(0, Some([1; 32]), None), result(1, Some([2; 32]), Some(TRANSFER_FAILED))]);
+    }
 
     fn to_32_bytes(hex_str: &str) -> [u8; 32] {
         let bytes = hex::decode(hex_str).unwrap();
@@ -382,8 +1272,9 @@ mod tests {
                 "6801dc29a7d1784f57c511369f84d68f04630bc7afcaa2b92c03272af26430fb7b93aaae22ce4f44818acb3345db276252ef71c7442cf1fe94d1d230191208cb".to_string(),
             ],
             vs: vec![0, 0, 1, 1],
+            signer_paths: vec![],
         };
-        let msg_str = serde_json::to_string(&msg_obj).unwrap();
+        let msg_str = near_sdk::serde_json::to_string(&msg_obj).unwrap();
         println!("{}", msg_str);
     }
 }
@@ -0,0 +1,183 @@
+use near_sdk::borsh::{self, BorshDeserialize};
+use near_sdk::collections::{LookupMap, LookupSet, TreeMap, Vector};
+use near_sdk::{env, near_bindgen};
+
+use crate::committee::{Committee, Quorum, VerificationPolicy, BEACON_ADDRESS_LEN, BEACON_PUBLIC_KEY_LEN};
+use crate::errors::*;
+use crate::root_cache::RootCache;
+use crate::utils::DEFAULT_COMMITTEE_RETENTION;
+use crate::*;
+
+/// Vault state layout of the first release.
+#[derive(BorshDeserialize)]
+struct VaultV0 {
+    tx_burn: LookupMap<[u8; 32], bool>,
+    beacons: TreeMap<u128, Vec<String>>,
+    total_credit_amount: LookupMap<String, u128>,
+    credit_amount: LookupMap<(String, String), u128>,
+    token_decimals: LookupMap<String, u8>,
+}
+
+#[near_bindgen]
+impl Vault {
+    /// migrate state
+    ///
+    /// upgrade state written by the first release, called by the vault account after deploying new code
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let mut old: VaultV0 = env::state_read().expect("Vault state not found");
+
+        // re-key committees under the same prefix as raw bytes
+        let committees = old.beacons.to_vec();
+        old.beacons.clear();
+        let mut beacons = TreeMap::new(StorageKey::BeaconHeight);
+        for (height, keys) in committees {
+            beacons.insert(&height, &committee_from_v0(&keys));
+        }
+
+        Self {
+            tx_burn: old.tx_burn,
+            beacons,
+            bridge_committees: TreeMap::new(StorageKey::BridgeHeight),
+            verification_policy: VerificationPolicy::Beacon,
+            quorum: Quorum::default(),
+            verified_roots: RootCache::new(),
+            total_credit_amount: old.total_credit_amount,
+            credit_amount: old.credit_amount,
+            token_decimals: old.token_decimals,
+            claimable: LookupMap::new(StorageKey::Claimable),
+            token_fees: LookupMap::new(StorageKey::TokenFee),
+            accrued_fees: LookupMap::new(StorageKey::AccruedFee),
+            treasury: env::predecessor_account_id(),
+            wrap_near: None,
+            unwrap_near_receivers: LookupSet::new(StorageKey::UnwrapNear),
+            nft_collections: LookupSet::new(StorageKey::NftCollection),
+            locked_nfts: LookupSet::new(StorageKey::LockedNft),
+            deposit_caps: LookupMap::new(StorageKey::DepositCap),
+            locked_balances: LookupMap::new(StorageKey::LockedBalance),
+            burn_history: Vector::new(StorageKey::BurnHistory),
+            storage_usage: storage::StorageUsage::default(),
+            pending_deposits: LookupMap::new(StorageKey::PendingDeposit),
+            pending_withdrawals: LookupMap::new(StorageKey::PendingWithdrawal),
+            // everything shielded so far is missing from `locked_balances`
+            ledger_tokens: Some(LookupSet::new(StorageKey::LedgerToken)),
+            recovering: LookupSet::new(StorageKey::Recovering),
+            shard_ids: LookupMap::new(StorageKey::ShardIds),
+            chain_id: String::new(),
+            legacy_inst_sunset: None,
+            admin: env::predecessor_account_id(),
+            committee_retention: DEFAULT_COMMITTEE_RETENTION,
+        }
+    }
+}
+
+/// convert hex committee of the first release, `new` stored public keys
+/// while swaps stored 32 bytes words holding beacon addresses
+fn committee_from_v0(keys: &[String]) -> Committee {
+    let words: Vec<Vec<u8>> = keys.iter()
+        .map(|key| hex::decode(key).expect(INVALID_BEACON_KEY))
+        .collect();
+    if words.iter().all(|word| word.len() == 32) {
+        Committee::Addresses(words.iter().map(|word| *array_ref![word, 32 - BEACON_ADDRESS_LEN, BEACON_ADDRESS_LEN]).collect())
+    } else if words.iter().all(|word| word.len() == BEACON_PUBLIC_KEY_LEN) {
+        Committee::from_hex(keys)
+    } else {
+        panic!("{}", INVALID_BEACON_KEY)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use near_sdk::borsh::BorshSerialize;
+    use near_sdk::json_types::U128;
+    use near_sdk::test_utils::{accounts, get_logs, VMContextBuilder};
+    use near_sdk::{testing_env, PromiseResult, RuntimeFeesConfig, VMConfig};
+
+    // writable twin of `VaultV0`
+    #[derive(BorshSerialize)]
+    struct VaultV0State {
+        tx_burn: LookupMap<[u8; 32], bool>,
+        beacons: TreeMap<u128, Vec<String>>,
+        total_credit_amount: LookupMap<String, u128>,
+        credit_amount: LookupMap<(String, String), u128>,
+        token_decimals: LookupMap<String, u8>,
+    }
+
+    /// vault migrated from a first release that already holds shielded `token.near`
+    fn migrated() -> Vault {
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(0)).build());
+        let mut old = VaultV0State {
+            tx_burn: LookupMap::new(StorageKey::Transaction),
+            beacons: TreeMap::new(StorageKey::BeaconHeight),
+            total_credit_amount: LookupMap::new(StorageKey::TokenAccountID),
+            credit_amount: LookupMap::new(StorageKey::TokenUserAccountID),
+            token_decimals: LookupMap::new(StorageKey::TokenDecimals),
+        };
+        old.beacons.insert(&0, &vec!["ab".repeat(64)]);
+        old.token_decimals.insert(&"token.near".to_string(), &9);
+        env::state_write(&old);
+
+        Vault::migrate()
+    }
+
+    fn with_balance(balance: u128) {
+        testing_env!(
+            VMContextBuilder::new().predecessor_account_id(accounts(0)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(format!("\"{}\"", balance).into_bytes())]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Locked balance of this token must be initialized after migrating")]
+    fn test_recover_surplus_after_migration() {
+        let mut vault = migrated();
+        assert!(!vault.is_locked_balance_tracked("token.near".to_string()));
+        vault.recover_surplus("token.near".parse().unwrap(), accounts(1));
+    }
+
+    #[test]
+    fn test_init_locked_balance_after_migration() {
+        let mut vault = migrated();
+        // funds bridged by the first release are not surplus
+        with_balance(1_000);
+        let report = &vault.on_proof_of_reserves(vec!["token.near".parse().unwrap()])[0];
+        assert_eq!((report.tracked, report.surplus), (false, U128(0)));
+        // payouts of untracked tokens may still go below zero
+        assert!(vault.unlock("token.near", 100).is_ok());
+
+        vault.init_locked_balance("token.near".to_string(), U128(900));
+        assert!(vault.is_locked_balance_tracked("token.near".to_string()));
+        assert!(vault.unlock("token.near", 1_000).is_err());
+        with_balance(1_000);
+        vault.on_recover_surplus("token.near".parse().unwrap(), accounts(1), U128(900));
+        assert_eq!(get_logs().last(), Some(&format!("recover token.near {} 100", accounts(1))));
+    }
+
+    #[test]
+    #[should_panic(expected = "Locked balance of this token is already tracked")]
+    fn test_init_locked_balance_of_new_vault() {
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(0)).build());
+        let mut vault = Vault::new(vec!["ab".repeat(64)], 0);
+        vault.init_locked_balance("token.near".to_string(), U128(900));
+    }
+
+    #[test]
+    fn test_committee_from_v0() {
+        let key = "ab".repeat(64);
+        assert_eq!(committee_from_v0(&[key]), Committee::PublicKeys(vec![[0xab; 64]]));
+
+        let word = format!("{}{}", "00".repeat(12), "cd".repeat(20));
+        assert_eq!(committee_from_v0(&[word]), Committee::Addresses(vec![[0xcd; 20]]));
+    }
+
+    #[test]
+    #[should_panic(expected = "Beacon keys must all be 64 bytes public keys or 20 bytes addresses")]
+    fn test_committee_from_v0_mixed() {
+        committee_from_v0(&["ab".repeat(64), "cd".repeat(32)]);
+    }
+}
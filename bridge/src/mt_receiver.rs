@@ -0,0 +1,261 @@
+use near_sdk::json_types::U128;
+use near_sdk::{serde_json, env, near_bindgen, AccountId, Gas, PromiseOrValue};
+
+use crate::errors::*;
+use crate::token_receiver::parse_deposit_msg;
+use crate::utils::mt_token_key;
+use crate::*;
+
+#[near_bindgen]
+impl Vault {
+    /// Callback on receiving NEP-245 multi tokens by this contract.
+    /// `msg` format is `TokenReceiverMessage`, every token id must be registered
+    /// with `set_mt_decimals` and is shielded as `contract:token_id` once the vault
+    /// balance of each id is checked against its deposit cap.
+    #[allow(unused_variables)]
+    pub fn mt_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        previous_owner_ids: Vec<AccountId>,
+        token_ids: Vec<String>,
+        amounts: Vec<U128>,
+        msg: String,
+    ) -> PromiseOrValue<Vec<U128>> {
+        let contract = env::predecessor_account_id();
+        assert_eq!(token_ids.len(), amounts.len(), "{}", INVALID_MT_BATCH);
+        let incognito_address = parse_deposit_msg(&msg);
+
+        for (token_id, amount) in token_ids.iter().zip(amounts.iter()) {
+            let token_key = mt_token_key(contract.as_str(), token_id);
+            assert!(self.token_decimals.get(&token_key).is_some(), "{}", MT_TOKEN_NOT_REGISTERED);
+            self.add_pending(&token_key, amount.0);
+        }
+
+        ext_mt::ext(contract.clone())
+            .with_static_gas(Gas(5_000_000_000_000))
+            .mt_batch_balance_of(
+                env::current_account_id(),
+                token_ids.clone(),
+            )
+            .then(ext_self::ext(env::current_account_id())
+                .with_static_gas(Gas(10_000_000_000_000))
+                .fallback_mt_deposit(
+                    incognito_address,
+                    contract,
+                    token_ids,
+                    amounts,
+                )).into()
+    }
+
+    /// emit shields of received multi tokens whose vault balance is within the deposit cap,
+    /// the others are returned unused, all of them when the balances cannot be read
+    #[private]
+    pub fn fallback_mt_deposit(
+        &mut self,
+        incognito_address: String,
+        contract: AccountId,
+        token_ids: Vec<String>,
+        amounts: Vec<U128>,
+    ) -> Vec<U128> {
+        for (token_id, amount) in token_ids.iter().zip(amounts.iter()) {
+            self.remove_pending(&mt_token_key(contract.as_str(), token_id), amount.0);
+        }
+        let balances = match env::promise_result(0) {
+            PromiseResult::Successful(result) => serde_json::from_slice::<Vec<U128>>(&result).ok(),
+            _ => None,
+        };
+        let balances = match balances {
+            Some(balances) if balances.len() == token_ids.len() => balances,
+            _ => return token_ids.iter().zip(amounts.iter())
+                .map(|(token_id, amount)| U128(self.refund_deposit(&mt_token_key(contract.as_str(), token_id), amount.0, MT_BALANCE_UNAVAILABLE)))
+                .collect(),
+        };
+
+        token_ids.iter().zip(amounts.iter()).zip(balances.iter())
+            .map(|((token_id, amount), balance)| {
+                let token_key = mt_token_key(contract.as_str(), token_id);
+                // registration is checked by `mt_on_transfer`
+                let decimals = self.token_decimals.get(&token_key).unwrap_or_default();
+                if !self.within_deposit_cap(&token_key, balance.0 / incognito_unit(decimals)) {
+                    return U128(self.refund_deposit(&token_key, amount.0, VALUE_EXCEEDED));
+                }
+                self.emit_shield(&incognito_address, &token_key, amount.0, decimals);
+                U128(0)
+            })
+            .collect()
+    }
+
+    // getters
+
+    /// get decimals registered for multi token id, none when it cannot be shielded
+    pub fn get_mt_decimals(&self, contract: AccountId, token_id: String) -> Option<u8> {
+        self.token_decimals.get(&mt_token_key(contract.as_str(), &token_id))
+    }
+
+    // setters
+
+    /// register decimals of multi token id, allowing it to be shielded
+    pub fn set_mt_decimals(&mut self, contract: AccountId, token_id: String, decimals: u8) {
+        self.assert_admin();
+        self.token_decimals.insert(&mt_token_key(contract.as_str(), &token_id), &decimals);
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use crate::utils::MT_WITHDRAW_METADATA;
+    use near_sdk::mock::VmAction;
+    use near_sdk::test_utils::{accounts, get_created_receipts, get_logs, VMContextBuilder};
+    use near_sdk::{testing_env, RuntimeFeesConfig, VMConfig};
+
+    fn setup() -> Vault {
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(0)).build());
+        let mut vault = Vault::new(test_utils::beacons(), 0);
+        vault.set_mt_decimals(accounts(2), "gold".to_string(), 18);
+        vault.set_mt_decimals(accounts(2), "silver".to_string(), 6);
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(2)).build());
+        vault
+    }
+
+    fn deposit_msg() -> String {
+        "{\"incognito_address\":\"incognito-address\"}".to_string()
+    }
+
+    fn with_balances(balances: &[u128]) {
+        let balances: Vec<U128> = balances.iter().copied().map(U128).collect();
+        testing_env!(
+            VMContextBuilder::new().predecessor_account_id(accounts(0)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(serde_json::to_vec(&balances).unwrap())]
+        );
+    }
+
+    #[test]
+    fn test_mt_on_transfer() {
+        let mut vault = setup();
+        let token_ids = vec!["gold".to_string(), "silver".to_string()];
+        let amounts = vec![U128(3 * 10u128.pow(18)), U128(7)];
+        let result = vault.mt_on_transfer(
+            accounts(1),
+            vec![accounts(1), accounts(1)],
+            token_ids.clone(),
+            amounts.clone(),
+            deposit_msg(),
+        );
+        // shields wait for the balance check
+        assert!(matches!(result, PromiseOrValue::Promise(_)));
+        assert!(get_logs().is_empty());
+
+        with_balances(&[3 * 10u128.pow(18), 7]);
+        let unused = vault.fallback_mt_deposit("incognito-address".to_string(), accounts(2), token_ids, amounts);
+        assert_eq!(unused, vec![U128(0), U128(0)]);
+        assert_eq!(get_logs(), vec![
+            format!("incognito-address {}:gold 3000000000", accounts(2)),
+            format!("incognito-address {}:silver 7", accounts(2)),
+        ]);
+        assert_eq!(vault.pending_deposits.get(&mt_token_key(accounts(2).as_str(), "gold")), Some(0));
+    }
+
+    #[test]
+    fn test_mt_on_transfer_deposit_cap() {
+        let mut vault = setup();
+        let token_ids = vec!["gold".to_string(), "silver".to_string()];
+        let amounts = vec![U128(3), U128(7)];
+        vault.mt_on_transfer(accounts(1), vec![accounts(1), accounts(1)], token_ids.clone(), amounts.clone(), deposit_msg());
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(0)).build());
+        let silver = mt_token_key(accounts(2).as_str(), "silver");
+        vault.set_deposit_cap(silver.clone(), Some(U128(10)));
+        with_balances(&[3, 11]);
+        // silver exceeds its cap and is refunded, gold is still shielded
+        let unused = vault.fallback_mt_deposit("incognito-address".to_string(), accounts(2), token_ids, amounts);
+        assert_eq!(unused, vec![U128(0), U128(7)]);
+        assert_eq!(vault.pending_deposits.get(&silver), Some(0));
+        assert_eq!(vault.get_locked_balance(silver.clone()), U128(0));
+        assert_eq!(get_logs().last(), Some(&format!("refund {} 7: {}", silver, VALUE_EXCEEDED)));
+    }
+
+    #[test]
+    fn test_mt_on_transfer_balance_unavailable() {
+        let mut vault = setup();
+        let gold = mt_token_key(accounts(2).as_str(), "gold");
+        vault.mt_on_transfer(accounts(1), vec![accounts(1)], vec!["gold".to_string()], vec![U128(3)], deposit_msg());
+        testing_env!(
+            VMContextBuilder::new().predecessor_account_id(accounts(0)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed]
+        );
+        let unused = vault.fallback_mt_deposit("incognito-address".to_string(), accounts(2), vec!["gold".to_string()], vec![U128(3)]);
+        assert_eq!(unused, vec![U128(3)]);
+        assert_eq!(vault.pending_deposits.get(&gold), Some(0));
+        assert_eq!(vault.get_locked_balance(gold), U128(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "Message in FT transfer must not be empty")]
+    fn test_mt_on_transfer_empty_msg() {
+        let mut vault = setup();
+        vault.mt_on_transfer(accounts(1), vec![accounts(1)], vec!["gold".to_string()], vec![U128(1)], String::new());
+    }
+
+    #[test]
+    #[should_panic(expected = "Multi token id is not registered")]
+    fn test_mt_on_transfer_not_registered() {
+        let mut vault = setup();
+        vault.mt_on_transfer(accounts(1), vec![accounts(1)], vec!["bronze".to_string()], vec![U128(1)], deposit_msg());
+    }
+
+    #[test]
+    #[should_panic(expected = "Token ids and amounts must have the same length")]
+    fn test_mt_on_transfer_length_mismatch() {
+        let mut vault = setup();
+        vault.mt_on_transfer(accounts(1), vec![accounts(1)], vec!["gold".to_string()], vec![], deposit_msg());
+    }
+
+    #[test]
+    fn test_failed_mt_payout_claimable() {
+        let mut vault = setup();
+        let token_key = mt_token_key(accounts(2).as_str(), "gold");
+        testing_env!(
+            VMContextBuilder::new().predecessor_account_id(accounts(1)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed]
+        );
+        assert!(!vault.on_withdraw_transfer(accounts(1), token_key.clone(), U128(5)));
+        assert_eq!(vault.get_claimable(accounts(1), token_key.clone()), U128(5));
+        assert_eq!(vault.get_locked_balance(token_key.clone()), U128(5));
+
+        drop(vault.claim_refund(token_key.clone()));
+        assert_eq!(vault.get_claimable(accounts(1), token_key.clone()), U128(0));
+        assert_eq!(vault.get_withdrawing_balance(token_key), U128(5));
+        let receipts = get_created_receipts();
+        assert_eq!(receipts[0].receiver_id, accounts(2));
+        assert!(matches!(
+            &receipts[0].actions[0],
+            VmAction::FunctionCall { function_name, args, .. } if function_name == "mt_transfer"
+                && serde_json::from_slice::<serde_json::Value>(args).unwrap()["token_id"] == "gold"
+        ));
+    }
+
+    #[test]
+    fn test_withdraw_mt() {
+        let mut vault = setup();
+        let token_key = mt_token_key(accounts(2).as_str(), "gold");
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(0)).build());
+        vault.set_token_fee(token_key.clone(), fees::TokenFee { shield_bps: 0, unshield_bps: 100 });
+        let inst = test_utils::withdraw_payload_inst(MT_WITHDRAW_METADATA, accounts(2).as_str(), "alice.near", 100, [1; 32], "gold");
+        let request = test_utils::shielded_request(&mut vault, &inst);
+        test_utils::attach_storage_deposit();
+        vault.withdraw(request, None);
+        assert!(vault.tx_burn.get(&[1; 32]).unwrap_or_default());
+        // fees are kept per token id in its own decimals
+        assert_eq!(vault.get_accrued_fees(token_key), U128(10u128.pow(9)));
+    }
+}
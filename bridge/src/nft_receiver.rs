@@ -0,0 +1,227 @@
+use near_contract_standards::non_fungible_token::core::NonFungibleTokenReceiver;
+use near_contract_standards::non_fungible_token::TokenId;
+use near_sdk::{env, near_bindgen, AccountId, Gas, Promise, PromiseOrValue};
+
+use crate::errors::*;
+use crate::token_receiver::parse_deposit_msg;
+use crate::inst::WithdrawInst;
+use crate::utils::NFT_WITHDRAW_METADATA;
+use crate::*;
+
+#[near_bindgen]
+impl NonFungibleTokenReceiver for Vault {
+    /// Callback on receiving an NFT of an allowed collection by this contract.
+    /// `msg` format is `TokenReceiverMessage`, the NFT stays locked until unshielded.
+    /// The deposit forwarded by the collection pays the lock's storage, the NFT is
+    /// returned when it does not.
+    #[allow(unused_variables)]
+    #[payable]
+    fn nft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        previous_owner_id: AccountId,
+        token_id: TokenId,
+        msg: String,
+    ) -> PromiseOrValue<bool> {
+        let collection = env::predecessor_account_id();
+        assert!(self.nft_collections.contains(&collection), "{}", NFT_COLLECTION_NOT_ALLOWED);
+        let incognito_address = parse_deposit_msg(&msg);
+
+        let storage_before = env::storage_usage();
+        self.locked_nfts.insert(&(collection.to_string(), token_id.clone()));
+        if !Self::storage_covered(storage_before) {
+            self.locked_nfts.remove(&(collection.to_string(), token_id.clone()));
+            env::log_str(format!("refund nft {} {}: {}", collection, token_id, STORAGE_DEPOSIT_REQUIRED).as_str());
+            return PromiseOrValue::Value(true);
+        }
+        self.charge_storage(Subsystem::Other, storage_before);
+        env::log_str(format!(
+            "nft {} {} {}",
+            incognito_address, collection, token_id
+        ).as_str());
+
+        // keep the token
+        PromiseOrValue::Value(false)
+    }
+}
+
+#[near_bindgen]
+impl Vault {
+    /// withdraw nft
+    ///
+    /// submit burn proof to release a locked NFT to its receiver,
+    /// the attached deposit pays the storage used
+    #[payable]
+    pub fn withdraw_nft(
+        &mut self,
+        unshield_info: InteractRequest,
+        bridge_info: Option<InteractRequest>,
+    ) -> Promise {
+        let storage_before = env::storage_usage();
+        // verify instruction
+        self.verify_committees(&unshield_info, &bridge_info);
+
+        // parse instruction, same layout as withdraw with the token id appended
+        let inst = self.strip_domain(hex::decode(&unshield_info.inst).unwrap_or_default()).unwrap_or_else(|err| panic!("{}", err));
+        let decoded = self.decode_withdraw_inst(&inst).unwrap_or_else(|err| panic!("{}", err));
+        let WithdrawInst { meta_type, token: collection, receiver: receiver_key, tx_id, payload, .. } = decoded.clone();
+
+        // validate metatype
+        if meta_type != NFT_WITHDRAW_METADATA {
+            panic!("{}", INVALID_METADATA);
+        }
+        let token_id = payload.expect(INVALID_INSTRUCTION);
+
+        // check tx burn used
+        if self.tx_burn.get(&tx_id).unwrap_or_default() {
+            panic!("{}", INVALID_TX_BURN);
+        }
+        self.tx_burn.insert(&tx_id, &true);
+        let index = self.record_burn(&decoded);
+        assert!(self.locked_nfts.remove(&(collection.clone(), token_id.clone())), "{}", NFT_NOT_LOCKED);
+
+        let receiver: AccountId = receiver_key.try_into().expect(INVALID_RECEIVER);
+        let collection: AccountId = collection.try_into().expect(INVALID_METADATA);
+        self.charge_storage(Subsystem::Burns, storage_before);
+        ext_nft::ext(collection.clone())
+            .with_attached_deposit(1)
+            .with_static_gas(Gas(10_000_000_000_000))
+            .nft_transfer(
+                receiver,
+                token_id.clone(),
+                None,
+                None,
+            )
+            .then(ext_self::ext(env::current_account_id())
+                .with_static_gas(Gas(5_000_000_000_000))
+                .on_withdraw_nft(
+                    tx_id,
+                    collection,
+                    token_id,
+                    index,
+                ))
+    }
+
+    /// lock the NFT again and release its burn proof when the transfer failed
+    #[private]
+    pub fn on_withdraw_nft(&mut self, tx_id: [u8; 32], collection: AccountId, token_id: TokenId, index: u64) -> bool {
+        if promise_succeeded(0) {
+            return true;
+        }
+        // undo what `withdraw_nft` charged, only the reverted burn record stays
+        let storage_before = env::storage_usage();
+        self.locked_nfts.insert(&(collection.to_string(), token_id));
+        self.tx_burn.remove(&tx_id);
+        self.revert_burn(index);
+        self.record_storage(Subsystem::Burns, storage_before);
+
+        false
+    }
+
+    // getters
+
+    /// check NFT collection can be shielded
+    pub fn is_nft_collection_allowed(&self, collection: AccountId) -> bool {
+        self.nft_collections.contains(&collection)
+    }
+
+    /// check NFT is locked in vault
+    pub fn is_nft_locked(&self, collection: AccountId, token_id: TokenId) -> bool {
+        self.locked_nfts.contains(&(collection.to_string(), token_id))
+    }
+
+    // setters
+
+    /// allow or disallow shielding NFTs of collection, locked NFTs can still be unshielded
+    pub fn set_nft_collection(&mut self, collection: AccountId, allowed: bool) {
+        self.assert_admin();
+        if allowed {
+            self.nft_collections.insert(&collection);
+        } else {
+            self.nft_collections.remove(&collection);
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use near_sdk::test_utils::{accounts, get_logs, VMContextBuilder};
+    use near_sdk::{testing_env, RuntimeFeesConfig, VMConfig};
+
+    fn setup() -> Vault {
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(0)).build());
+        let mut vault = Vault::new(test_utils::beacons(), 0);
+        vault.set_nft_collection(accounts(2), true);
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(2)).build());
+        test_utils::attach_storage_deposit();
+        vault
+    }
+
+    fn deposit_msg() -> String {
+        "{\"incognito_address\":\"incognito-address\"}".to_string()
+    }
+
+    #[test]
+    fn test_nft_on_transfer() {
+        let mut vault = setup();
+        let result = vault.nft_on_transfer(accounts(1), accounts(1), "7".to_string(), deposit_msg());
+        assert!(matches!(result, PromiseOrValue::Value(false)));
+        assert!(vault.is_nft_locked(accounts(2), "7".to_string()));
+        assert_eq!(get_logs(), vec![format!("nft incognito-address {} 7", accounts(2))]);
+    }
+
+    #[test]
+    fn test_nft_on_transfer_without_storage_deposit() {
+        let mut vault = setup();
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(2)).build());
+        let result = vault.nft_on_transfer(accounts(1), accounts(1), "7".to_string(), deposit_msg());
+        // returned to its owner instead of locked at the vault's expense
+        assert!(matches!(result, PromiseOrValue::Value(true)));
+        assert!(!vault.is_nft_locked(accounts(2), "7".to_string()));
+        assert_eq!(get_logs(), vec![format!("refund nft {} 7: {}", accounts(2), STORAGE_DEPOSIT_REQUIRED)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "NFT collection is not allowed")]
+    fn test_nft_on_transfer_not_allowed() {
+        let mut vault = setup();
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(3)).build());
+        vault.nft_on_transfer(accounts(1), accounts(1), "7".to_string(), deposit_msg());
+    }
+
+    #[test]
+    fn test_withdraw_nft() {
+        let mut vault = setup();
+        vault.nft_on_transfer(accounts(1), accounts(1), "7".to_string(), deposit_msg());
+        let inst = test_utils::withdraw_payload_inst(NFT_WITHDRAW_METADATA, accounts(2).as_str(), "alice.near", 0, [1; 32], "7");
+        let request = test_utils::shielded_request(&mut vault, &inst);
+        test_utils::attach_storage_deposit();
+        vault.withdraw_nft(request, None);
+        assert!(!vault.is_nft_locked(accounts(2), "7".to_string()));
+        assert!(vault.tx_burn.get(&[1; 32]).unwrap_or_default());
+
+        // failed transfer can be withdrawn again
+        testing_env!(
+            VMContextBuilder::new().build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed]
+        );
+        assert!(!vault.on_withdraw_nft([1; 32], accounts(2), "7".to_string(), 0));
+        assert!(vault.get_burns(0, 1, None, None).records[0].reverted);
+        assert!(vault.is_nft_locked(accounts(2), "7".to_string()));
+        assert!(!vault.tx_burn.get(&[1; 32]).unwrap_or_default());
+    }
+
+    #[test]
+    #[should_panic(expected = "NFT is not locked in vault")]
+    fn test_withdraw_nft_not_locked() {
+        let mut vault = setup();
+        let inst = test_utils::withdraw_payload_inst(NFT_WITHDRAW_METADATA, accounts(2).as_str(), "alice.near", 0, [1; 32], "7");
+        let request = test_utils::shielded_request(&mut vault, &inst);
+        vault.withdraw_nft(request, None);
+    }
+}
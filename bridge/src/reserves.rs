@@ -0,0 +1,505 @@
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, AccountId, Gas, Promise, PromiseOrValue, PromiseResult};
+
+use crate::errors::*;
+use crate::utils::{incognito_unit, NEAR_ADDRESS};
+use crate::*;
+
+// NEAR has 24 decimals
+const NEAR_DECIMALS: u8 = 24;
+
+/// Balance of one token held by the vault against what it owes, in the token's own decimals
+/// or yoctoNEAR. `balance` is none when the token contract could not be queried.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ReserveReport {
+    pub token: String,
+    pub balance: Option<U128>,
+    // shielded and not unshielded yet, including claimable refunds
+    pub locked: U128,
+    // received by shields still waiting for their callback
+    pub pending: U128,
+    // credited by burn proofs, moved out of `locked`
+    pub credited: U128,
+    // paid out by transfers still waiting for their callback
+    pub withdrawing: U128,
+    // protocol fees not claimed yet
+    pub fees: U128,
+    // locked balance covers every shield of the token, surplus is zero otherwise
+    pub tracked: bool,
+    pub surplus: U128,
+    pub deficit: U128,
+}
+
+#[near_bindgen]
+impl Vault {
+    /// proof of reserves
+    ///
+    /// compare balances of `tokens` and native NEAR held by the vault with its ledgers,
+    /// every report is also logged
+    pub fn proof_of_reserves(&self, tokens: Vec<AccountId>) -> PromiseOrValue<Vec<ReserveReport>> {
+        let mut balances = tokens.iter().map(|token| ext_ft::ext(token.clone())
+            .with_static_gas(Gas(5_000_000_000_000))
+            .ft_balance_of(
+                env::current_account_id(),
+            ));
+        match balances.next() {
+            Some(first) => balances
+                .fold(first, |all, balance| all.and(balance))
+                .then(ext_self::ext(env::current_account_id())
+                    .with_static_gas(Gas(10_000_000_000_000))
+                    .on_proof_of_reserves(
+                        tokens,
+                    ))
+                .into(),
+            None => PromiseOrValue::Value(vec![self.near_reserve_report()]),
+        }
+    }
+
+    /// build reports from the `ft_balance_of` results, native NEAR last
+    #[private]
+    pub fn on_proof_of_reserves(&self, tokens: Vec<AccountId>) -> Vec<ReserveReport> {
+        let mut reports: Vec<ReserveReport> = tokens.iter().enumerate()
+            .map(|(i, token)| {
+                let balance = match env::promise_result(i as u64) {
+                    PromiseResult::Successful(result) => near_sdk::serde_json::from_slice::<U128>(&result).ok().map(|balance| balance.0),
+                    _ => None,
+                };
+                self.reserve_report(token.as_str(), balance)
+            })
+            .collect();
+        reports.push(self.near_reserve_report());
+
+        reports
+    }
+
+    /// recover surplus
+    ///
+    /// send tokens transferred to the vault without shielding them to `receiver`,
+    /// only the balance above everything the vault owes can be moved, one recovery
+    /// per token at a time
+    pub fn recover_surplus(&mut self, token: AccountId, receiver: AccountId) -> Promise {
+        self.assert_admin();
+        assert!(self.is_locked_balance_tracked(token.to_string()), "{}", LEDGER_NOT_INITIALIZED);
+        assert!(self.recovering.insert(&token.to_string()), "{}", RECOVERY_IN_PROGRESS);
+        let owed = self.owed(token.as_str());
+        ext_ft::ext(token.clone())
+            .with_static_gas(Gas(5_000_000_000_000))
+            .ft_balance_of(
+                env::current_account_id(),
+            )
+            .then(ext_self::ext(env::current_account_id())
+                .with_static_gas(Gas(15_000_000_000_000))
+                .on_recover_surplus(
+                    token,
+                    receiver,
+                    U128(owed),
+                ))
+    }
+
+    /// transfer the surplus left by the balance read in `recover_surplus`, above what the
+    /// vault owed when it was requested or owes now, whichever is higher, as the balance
+    /// may be read before payouts settled in between
+    #[private]
+    pub fn on_recover_surplus(&mut self, token: AccountId, receiver: AccountId, owed: U128) -> PromiseOrValue<U128> {
+        let balance = match env::promise_result(0) {
+            PromiseResult::Successful(result) => near_sdk::serde_json::from_slice::<U128>(&result).ok().map(|balance| balance.0),
+            _ => None,
+        };
+        let owed = owed.0.max(self.owed(token.as_str()));
+        let surplus = balance.unwrap_or_default().saturating_sub(owed);
+        if surplus == 0 {
+            // nothing in flight, another recovery may start
+            self.recovering.remove(&token.to_string());
+            env::log_str(NO_SURPLUS);
+            return PromiseOrValue::Value(U128(0));
+        }
+        env::log_str(format!("recover {} {} {}", token, receiver, surplus).as_str());
+
+        // owed until the transfer settles so reports and withdraws meanwhile keep it
+        self.add_withdrawing(token.as_str(), surplus);
+        ext_ft::ext(token.clone())
+            .with_attached_deposit(1)
+            .with_static_gas(Gas(5_000_000_000_000))
+            .ft_transfer(
+                receiver,
+                U128(surplus),
+                None,
+            )
+            .then(ext_self::ext(env::current_account_id())
+                .with_static_gas(Gas(5_000_000_000_000))
+                .on_surplus_recovered(
+                    token,
+                    U128(surplus),
+                ))
+            .into()
+    }
+
+    /// settle a surplus transfer, a failed one leaves the surplus in the vault to recover again
+    #[private]
+    pub fn on_surplus_recovered(&mut self, token: AccountId, amount: U128) -> U128 {
+        self.remove_withdrawing(token.as_str(), amount.0);
+        self.recovering.remove(&token.to_string());
+        if promise_succeeded(0) {
+            return amount;
+        }
+        env::log_str(format!("recover {} {} failed", token, amount.0).as_str());
+
+        U128(0)
+    }
+
+    // getters
+
+    /// get amount of token shielded and not unshielded yet, `NEAR_ADDRESS` for native NEAR
+    pub fn get_locked_balance(&self, token: String) -> U128 {
+        U128(self.locked_balances.get(&token).unwrap_or_default())
+    }
+
+    /// check locked balance of token covers everything shielded, always true unless migrated
+    pub fn is_locked_balance_tracked(&self, token: String) -> bool {
+        match &self.ledger_tokens {
+            Some(tokens) => tokens.contains(&token),
+            None => true,
+        }
+    }
+
+    /// get amount of token paid out by transfers still waiting for their callback
+    pub fn get_withdrawing_balance(&self, token: String) -> U128 {
+        U128(self.pending_withdrawals.get(&token).unwrap_or_default())
+    }
+
+    // setters
+
+    /// set locked balance of a token shielded before migrating, to everything owed to
+    /// Incognito holders and claimants of it, and track it from now on
+    pub fn init_locked_balance(&mut self, token: String, locked: U128) {
+        self.assert_admin();
+        assert!(!self.is_locked_balance_tracked(token.clone()), "{}", LEDGER_ALREADY_INITIALIZED);
+        self.locked_balances.insert(&token, &locked.0);
+        if let Some(tokens) = self.ledger_tokens.as_mut() {
+            tokens.insert(&token);
+        }
+    }
+}
+
+impl Vault {
+    /// locked, pending, credited, withdrawing and fee ledgers of token
+    pub(crate) fn ledgers(&self, token: &str) -> [u128; 5] {
+        [
+            self.locked_balances.get(&token.to_string()).unwrap_or_default(),
+            self.pending_deposits.get(&token.to_string()).unwrap_or_default(),
+            // credits are in Incognito's 9 decimals
+            self.total_credit_amount.get(&token.to_string()).unwrap_or_default()
+                .saturating_mul(self.credit_unit(token)),
+            self.pending_withdrawals.get(&token.to_string()).unwrap_or_default(),
+            self.accrued_fees.get(&token.to_string()).unwrap_or_default(),
+        ]
+    }
+
+    /// everything the vault owes of token, in the token's own decimals or yoctoNEAR
+    fn owed(&self, token: &str) -> u128 {
+        self.ledgers(token).iter().fold(0, |owed, amount| owed.saturating_add(*amount))
+    }
+
+    /// NEAR staked for the vault's storage is not available to pay anything owed
+    fn near_reserve_report(&self) -> ReserveReport {
+        let staked = env::storage_usage() as u128 * env::storage_byte_cost();
+        self.reserve_report(NEAR_ADDRESS, Some(env::account_balance().saturating_sub(staked)))
+    }
+
+    fn reserve_report(&self, token: &str, balance: Option<u128>) -> ReserveReport {
+        let [locked, pending, credited, withdrawing, fees] = self.ledgers(token);
+        let owed = self.owed(token);
+        let tracked = self.is_locked_balance_tracked(token.to_string());
+        let (surplus, deficit) = match balance {
+            // shields before migrating are missing from `locked` until initialized
+            Some(balance) if tracked => (balance.saturating_sub(owed), owed.saturating_sub(balance)),
+            Some(balance) => (0, owed.saturating_sub(balance)),
+            None => (0, 0),
+        };
+        env::log_str(format!(
+            "reserves {} balance {} owed {} surplus {} deficit {}",
+            token, balance.map_or("unknown".to_string(), |balance| balance.to_string()), owed, surplus, deficit
+        ).as_str());
+
+        ReserveReport {
+            token: token.to_string(),
+            balance: balance.map(U128),
+            locked: U128(locked),
+            pending: U128(pending),
+            credited: U128(credited),
+            withdrawing: U128(withdrawing),
+            fees: U128(fees),
+            tracked,
+            surplus: U128(surplus),
+            deficit: U128(deficit),
+        }
+    }
+
+    /// units of token per credited unit, credits are in Incognito's 9 decimals
+    pub(crate) fn credit_unit(&self, token: &str) -> u128 {
+        if token == NEAR_ADDRESS {
+            return incognito_unit(NEAR_DECIMALS);
+        }
+        incognito_unit(self.token_decimals.get(&token.to_string()).unwrap_or_default())
+    }
+
+    /// record `amount` of token as owed to Incognito holders or claimants
+    pub(crate) fn lock(&mut self, token: &str, amount: u128) {
+        let locked = self.locked_balances.get(&token.to_string()).unwrap_or_default();
+        self.locked_balances.insert(&token.to_string(), &(locked + amount));
+    }
+
+    /// record `amount` of token received by a shield before its callback ran,
+    /// a callback that panics leaves it pending which only shrinks what can be recovered
+    pub(crate) fn add_pending(&mut self, token: &str, amount: u128) {
+        let pending = self.pending_deposits.get(&token.to_string()).unwrap_or_default();
+        self.pending_deposits.insert(&token.to_string(), &(pending + amount));
+    }
+
+    pub(crate) fn remove_pending(&mut self, token: &str, amount: u128) {
+        let pending = self.pending_deposits.get(&token.to_string()).unwrap_or_default();
+        self.pending_deposits.insert(&token.to_string(), &pending.saturating_sub(amount));
+    }
+
+    /// release `amount` of token paid out, only tokens shielded before migrating and not
+    /// initialized yet may go below zero
+    pub(crate) fn unlock(&mut self, token: &str, amount: u128) -> Result<(), &'static str> {
+        let locked = self.locked_balances.get(&token.to_string()).unwrap_or_default();
+        if locked < amount && self.is_locked_balance_tracked(token.to_string()) {
+            return Err(LOCKED_BALANCE_EXCEEDED);
+        }
+        self.locked_balances.insert(&token.to_string(), &locked.saturating_sub(amount));
+
+        Ok(())
+    }
+
+    /// record `amount` of token sent by a transfer whose callback has not run yet
+    pub(crate) fn add_withdrawing(&mut self, token: &str, amount: u128) {
+        let withdrawing = self.pending_withdrawals.get(&token.to_string()).unwrap_or_default();
+        self.pending_withdrawals.insert(&token.to_string(), &(withdrawing + amount));
+    }
+
+    /// settle a transfer recorded by `add_withdrawing` from its callback
+    pub(crate) fn remove_withdrawing(&mut self, token: &str, amount: u128) {
+        let withdrawing = self.pending_withdrawals.get(&token.to_string()).unwrap_or_default();
+        self.pending_withdrawals.insert(&token.to_string(), &withdrawing.saturating_sub(amount));
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::fees::TokenFee;
+    use crate::test_utils;
+    use crate::utils::BURN_METADATA;
+    use near_sdk::test_utils::{accounts, get_logs, VMContextBuilder};
+    use near_sdk::{testing_env, RuntimeFeesConfig, VMConfig};
+
+    fn setup() -> Vault {
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(0)).build());
+        let mut vault = Vault::new(test_utils::beacons(), 0);
+        vault.token_decimals.insert(&"token.near".to_string(), &18);
+        vault
+    }
+
+    #[test]
+    fn test_locked_ledger() {
+        let mut vault = setup();
+        vault.set_token_fee("token.near".to_string(), TokenFee { shield_bps: 100, unshield_bps: 100 });
+        vault.emit_shield("incognito-address", "token.near", 10_000 * 10u128.pow(9), 18);
+        assert_eq!(vault.get_locked_balance("token.near".to_string()), U128(9_900 * 10u128.pow(9)));
+
+        let inst = test_utils::withdraw_inst("token.near", "alice.near", 1_000, [1; 32]);
+        let request = test_utils::verified_request(&mut vault, &inst);
+        test_utils::attach_storage_deposit();
+        vault.withdraw(request, None);
+        // the unshield fee stays in vault as accrued fees
+        assert_eq!(vault.get_locked_balance("token.near".to_string()), U128(8_910 * 10u128.pow(9)));
+    }
+
+    #[test]
+    fn test_withdraw_above_locked() {
+        let mut vault = setup();
+        vault.emit_shield("incognito-address", "token.near", 999 * 10u128.pow(9), 18);
+        let inst = test_utils::withdraw_inst("token.near", "alice.near", 1_000, [1; 32]);
+        let request = test_utils::verified_request(&mut vault, &inst);
+        let before = env::storage_usage();
+        assert_eq!(vault.try_withdraw(&request, &None).err(), Some(LOCKED_BALANCE_EXCEEDED));
+        assert_eq!(env::storage_usage(), before);
+        assert_eq!(vault.get_locked_balance("token.near".to_string()), U128(999 * 10u128.pow(9)));
+    }
+
+    #[test]
+    fn test_withdrawing_owed_until_settled() {
+        let mut vault = setup();
+        vault.emit_shield("incognito-address", "token.near", 10_000 * 10u128.pow(9), 18);
+        let inst = test_utils::withdraw_inst("token.near", "alice.near", 1_000, [1; 32]);
+        let request = test_utils::verified_request(&mut vault, &inst);
+        test_utils::attach_storage_deposit();
+        vault.withdraw(request, None);
+        assert_eq!(vault.get_withdrawing_balance("token.near".to_string()), U128(1_000 * 10u128.pow(9)));
+
+        // the payout has not arrived, the balance still holds it
+        with_balance(&format!("\"{}\"", 10_000 * 10u128.pow(9)));
+        let reports = vault.on_proof_of_reserves(vec!["token.near".parse().unwrap()]);
+        assert_eq!(reports[0].withdrawing, U128(1_000 * 10u128.pow(9)));
+        assert_eq!((reports[0].surplus, reports[0].deficit), (U128(0), U128(0)));
+
+        // a failed payout becomes claimable and stays owed
+        testing_env!(
+            VMContextBuilder::new().build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed]
+        );
+        let alice: AccountId = "alice.near".parse().unwrap();
+        assert!(!vault.on_withdraw_transfer(alice.clone(), "token.near".to_string(), U128(1_000 * 10u128.pow(9))));
+        assert_eq!(vault.get_withdrawing_balance("token.near".to_string()), U128(0));
+        assert_eq!(vault.get_claimable(alice, "token.near".to_string()), U128(1_000 * 10u128.pow(9)));
+        assert_eq!(vault.get_locked_balance("token.near".to_string()), U128(10_000 * 10u128.pow(9)));
+    }
+
+    #[test]
+    fn test_reserves_after_burn_proof() {
+        let mut vault = setup();
+        vault.emit_shield("incognito-address", "token.near", 10_000 * 10u128.pow(9), 18);
+        let mut inst = test_utils::withdraw_inst("token.near", "alice.near", 1_000, [1; 32]);
+        inst[0] = BURN_METADATA;
+        let request = test_utils::verified_request(&mut vault, &inst);
+        test_utils::attach_storage_deposit();
+        vault.submit_burn_proof(request, None);
+
+        testing_env!(
+            VMContextBuilder::new().build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(format!("\"{}\"", 10_000 * 10u128.pow(9)).into_bytes())]
+        );
+        let reports = vault.on_proof_of_reserves(vec!["token.near".parse().unwrap()]);
+        assert_eq!(reports[0].locked, U128(9_000 * 10u128.pow(9)));
+        assert_eq!(reports[0].credited, U128(1_000 * 10u128.pow(9)));
+        assert_eq!((reports[0].surplus, reports[0].deficit), (U128(0), U128(0)));
+    }
+
+    #[test]
+    fn test_on_proof_of_reserves() {
+        let mut vault = setup();
+        vault.lock("token.near", 700);
+        vault.total_credit_amount.insert(&"token.near".to_string(), &1);
+        let staked = env::storage_usage() as u128 * env::storage_byte_cost();
+        testing_env!(
+            VMContextBuilder::new().storage_usage(env::storage_usage()).account_balance(staked + 5).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(b"\"500\"".to_vec()), PromiseResult::Failed]
+        );
+        let reports = vault.on_proof_of_reserves(vec!["token.near".parse().unwrap(), accounts(2)]);
+        assert_eq!(reports[0].credited, U128(10u128.pow(9)));
+        assert_eq!(reports[0].deficit, U128(10u128.pow(9) + 200));
+        assert_eq!(reports[1].balance, None);
+        assert_eq!((reports[2].token.as_str(), reports[2].surplus), (NEAR_ADDRESS, U128(5)));
+        assert_eq!(get_logs()[0], format!("reserves token.near balance 500 owed {} surplus 0 deficit {}", 10u128.pow(9) + 700, 10u128.pow(9) + 200));
+    }
+
+    fn with_balance(balance: &str) {
+        testing_env!(
+            VMContextBuilder::new().build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(balance.as_bytes().to_vec())]
+        );
+    }
+
+    #[test]
+    fn test_recover_surplus() {
+        let mut vault = setup();
+        vault.lock("token.near", 700);
+        vault.add_pending("token.near", 100);
+        with_balance("\"1000\"");
+        vault.on_recover_surplus("token.near".parse().unwrap(), accounts(1), U128(800));
+        assert_eq!(get_logs().last(), Some(&format!("recover token.near {} 200", accounts(1))));
+        // owed until the transfer settles
+        assert_eq!(vault.get_withdrawing_balance("token.near".to_string()), U128(200));
+    }
+
+    #[test]
+    #[should_panic(expected = "A surplus recovery of this token has not settled yet")]
+    fn test_recover_surplus_in_progress() {
+        let mut vault = setup();
+        vault.recover_surplus("token.near".parse().unwrap(), accounts(1));
+        vault.recover_surplus("token.near".parse().unwrap(), accounts(1));
+    }
+
+    #[test]
+    fn test_recover_surplus_stale_balance() {
+        let mut vault = setup();
+        vault.lock("token.near", 1000);
+        let owed = vault.owed("token.near");
+        // a payout settles after the balance was read
+        vault.unlock("token.near", 300).unwrap();
+        with_balance("\"1000\"");
+        vault.on_recover_surplus("token.near".parse().unwrap(), accounts(1), U128(owed));
+        assert_eq!(get_logs().last(), Some(&NO_SURPLUS.to_string()));
+        assert_eq!(vault.get_withdrawing_balance("token.near".to_string()), U128(0));
+    }
+
+    #[test]
+    fn test_surplus_recovered_failed() {
+        let mut vault = setup();
+        vault.recover_surplus("token.near".parse().unwrap(), accounts(1));
+        with_balance("\"200\"");
+        vault.on_recover_surplus("token.near".parse().unwrap(), accounts(1), U128(0));
+        testing_env!(
+            VMContextBuilder::new().predecessor_account_id(accounts(0)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed]
+        );
+        assert_eq!(vault.on_surplus_recovered("token.near".parse().unwrap(), U128(200)), U128(0));
+        assert_eq!(vault.get_withdrawing_balance("token.near".to_string()), U128(0));
+        // the surplus is still in the vault and can be recovered again
+        vault.recover_surplus("token.near".parse().unwrap(), accounts(1));
+    }
+
+    #[test]
+    fn test_recover_surplus_after_settled() {
+        let mut vault = setup();
+        vault.lock("token.near", 700);
+        vault.add_withdrawing("token.near", 300);
+        with_balance("\"1000\"");
+        assert_eq!(vault.on_proof_of_reserves(vec!["token.near".parse().unwrap()])[0].surplus, U128(0));
+
+        with_balance("\"1000\"");
+        vault.remove_withdrawing("token.near", 300);
+        vault.on_recover_surplus("token.near".parse().unwrap(), accounts(1), U128(700));
+        assert_eq!(get_logs().last(), Some(&format!("recover token.near {} 300", accounts(1))));
+    }
+
+    #[test]
+    fn test_recover_surplus_none() {
+        let mut vault = setup();
+        vault.lock("token.near", 1000);
+        vault.recover_surplus("token.near".parse().unwrap(), accounts(1));
+        with_balance("\"1000\"");
+        let result = vault.on_recover_surplus("token.near".parse().unwrap(), accounts(1), U128(1000));
+        assert!(matches!(result, PromiseOrValue::Value(U128(0))));
+        assert_eq!(get_logs(), vec![NO_SURPLUS.to_string()]);
+        // nothing in flight, the next recovery may start
+        assert!(!vault.recovering.contains(&"token.near".to_string()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only admin can call this method")]
+    fn test_recover_surplus_not_admin() {
+        let mut vault = setup();
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).build());
+        vault.recover_surplus("token.near".parse().unwrap(), accounts(1));
+    }
+}
@@ -0,0 +1,129 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::{LookupMap, Vector};
+use near_sdk::env;
+
+use crate::committee::Quorum;
+use crate::utils::{VERIFIED_ROOT_CAPACITY, VERIFIED_ROOT_TTL};
+use crate::{InteractRequest, StorageKey};
+
+/// Block roots whose signatures already passed verification, so further instructions
+/// of the same beacon block only need the merkle inclusion check.
+///
+/// At most `VERIFIED_ROOT_CAPACITY` roots are kept, the oldest is overwritten first,
+/// and each root expires `VERIFIED_ROOT_TTL` NEAR blocks after it was verified.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct RootCache {
+    // root key => NEAR block height the root expires at
+    expiries: LookupMap<[u8; 32], u64>,
+    // ring buffer of cached root keys
+    keys: Vector<[u8; 32]>,
+    // slot overwritten by the next insert once the buffer is full
+    next: u64,
+}
+
+impl RootCache {
+    pub fn new() -> Self {
+        Self {
+            expiries: LookupMap::new(StorageKey::VerifiedRoot),
+            keys: Vector::new(StorageKey::VerifiedRootKeys),
+            next: 0,
+        }
+    }
+
+    pub fn contains(&self, key: &[u8; 32]) -> bool {
+        matches!(self.expiries.get(key), Some(expiry) if env::block_height() < expiry)
+    }
+
+    /// cache `key`, a key already in the ring keeps its slot and only has its expiry refreshed
+    pub fn insert(&mut self, key: &[u8; 32]) {
+        if self.expiries.contains_key(key) {
+            self.expiries.insert(key, &(env::block_height() + VERIFIED_ROOT_TTL));
+            return;
+        }
+        if self.keys.len() < VERIFIED_ROOT_CAPACITY {
+            self.keys.push(key);
+        } else {
+            let evicted = self.keys.replace(self.next, key);
+            self.expiries.remove(&evicted);
+        }
+        self.next = (self.next + 1) % VERIFIED_ROOT_CAPACITY;
+        self.expiries.insert(key, &(env::block_height() + VERIFIED_ROOT_TTL));
+    }
+
+    pub fn len(&self) -> u64 {
+        self.keys.len()
+    }
+}
+
+/// cache key of the block signed in `request`, bound to the committee kind and quorum
+/// so a quorum update invalidates roots verified under the previous one
+pub fn root_key(request: &InteractRequest, bridge: bool, quorum: &Quorum) -> [u8; 32] {
+    let mut data = vec![bridge as u8];
+    data.extend_from_slice(&request.height.to_be_bytes());
+    data.extend_from_slice(&quorum.height.to_be_bytes());
+    data.extend_from_slice(&request.blk_data);
+    data.extend_from_slice(&request.inst_root);
+    env::keccak256_array(&data)
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    #[test]
+    fn test_root_expiry() {
+        testing_env!(VMContextBuilder::new().block_index(10).build());
+        let mut cache = RootCache::new();
+        cache.insert(&[1; 32]);
+        assert!(cache.contains(&[1; 32]));
+        assert!(!cache.contains(&[2; 32]));
+
+        testing_env!(VMContextBuilder::new().block_index(10 + VERIFIED_ROOT_TTL).build());
+        assert!(!cache.contains(&[1; 32]));
+    }
+
+    #[test]
+    fn test_root_eviction() {
+        testing_env!(VMContextBuilder::new().build());
+        let mut cache = RootCache::new();
+        for i in 0..=VERIFIED_ROOT_CAPACITY {
+            let mut key = [0u8; 32];
+            key[..8].copy_from_slice(&i.to_be_bytes());
+            cache.insert(&key);
+        }
+        assert_eq!(cache.len(), VERIFIED_ROOT_CAPACITY);
+        assert!(!cache.contains(&[0; 32]));
+        let mut last = [0u8; 32];
+        last[..8].copy_from_slice(&VERIFIED_ROOT_CAPACITY.to_be_bytes());
+        assert!(cache.contains(&last));
+    }
+
+    #[test]
+    fn test_root_refresh() {
+        testing_env!(VMContextBuilder::new().build());
+        let mut cache = RootCache::new();
+        let key = |i: u64| {
+            let mut key = [0u8; 32];
+            key[..8].copy_from_slice(&i.to_be_bytes());
+            key
+        };
+        cache.insert(&key(0));
+        cache.insert(&key(1));
+        // reinserting refreshes the slot it already has
+        cache.insert(&key(0));
+        assert_eq!(cache.len(), 2);
+
+        for i in 2..VERIFIED_ROOT_CAPACITY {
+            cache.insert(&key(i));
+        }
+        assert_eq!(cache.len(), VERIFIED_ROOT_CAPACITY);
+        assert!(cache.contains(&key(0)));
+        assert!(cache.contains(&key(1)));
+
+        cache.insert(&key(VERIFIED_ROOT_CAPACITY));
+        assert!(!cache.contains(&key(0)));
+        assert!(cache.contains(&key(1)));
+    }
+}
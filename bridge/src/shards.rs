@@ -0,0 +1,75 @@
+use near_sdk::near_bindgen;
+
+use crate::errors::*;
+use crate::inst::{decode_withdraw, WithdrawInst};
+use crate::utils::DEFAULT_SHARD_ID;
+use crate::*;
+
+#[near_bindgen]
+impl Vault {
+    // getters
+
+    /// get shard ids instructions of metadata type are accepted from
+    pub fn get_shard_ids(&self, meta_type: u8) -> Vec<u8> {
+        self.shard_ids.get(&meta_type).unwrap_or_else(|| vec![DEFAULT_SHARD_ID])
+    }
+
+    // setters
+
+    /// accept instructions of metadata type from `shard_ids` only, none to restore the default shard
+    pub fn set_shard_ids(&mut self, meta_type: u8, shard_ids: Option<Vec<u8>>) {
+        self.assert_admin();
+        match shard_ids {
+            Some(shard_ids) => self.shard_ids.insert(&meta_type, &shard_ids),
+            None => self.shard_ids.remove(&meta_type),
+        };
+    }
+}
+
+impl Vault {
+    /// decode withdraw instruction with the registered layouts, rejecting shards not accepted
+    pub(crate) fn decode_withdraw_inst(&self, inst: &[u8]) -> Result<WithdrawInst, &'static str> {
+        let decoded = decode_withdraw(inst)?;
+        if !self.get_shard_ids(decoded.meta_type).contains(&decoded.shard_id) {
+            return Err(INVALID_METADATA);
+        }
+
+        Ok(decoded)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use crate::utils::WITHDRAW_METADATA;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    fn setup() -> Vault {
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(0)).build());
+        Vault::new(test_utils::beacons(), 0)
+    }
+
+    #[test]
+    fn test_shard_ids() {
+        let mut vault = setup();
+        let mut inst = test_utils::withdraw_inst("token.near", "alice.near", 5, [1; 32]);
+        inst[1] = 2;
+        assert_eq!(vault.decode_withdraw_inst(&inst), Err(INVALID_METADATA));
+
+        vault.set_shard_ids(WITHDRAW_METADATA, Some(vec![1, 2]));
+        assert_eq!(vault.decode_withdraw_inst(&inst).unwrap().shard_id, 2);
+
+        vault.set_shard_ids(WITHDRAW_METADATA, None);
+        assert_eq!(vault.get_shard_ids(WITHDRAW_METADATA), vec![DEFAULT_SHARD_ID]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only admin can call this method")]
+    fn test_set_shard_ids_not_admin() {
+        let mut vault = setup();
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).build());
+        vault.set_shard_ids(WITHDRAW_METADATA, Some(vec![2]));
+    }
+}
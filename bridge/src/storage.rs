@@ -0,0 +1,191 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, Promise};
+
+use crate::errors::*;
+use crate::*;
+
+/// Subsystems whose storage is tracked, callers growing them pay its staking
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Subsystem {
+    // used burn tx ids, burn history and credits
+    Burns,
+    // beacon and bridge committees
+    Committees,
+    // settings and shield ledgers, reported as what the others leave
+    Other,
+}
+
+/// Bytes added by each subsystem since storage is tracked
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct StorageUsage {
+    pub burns: u64,
+    pub committees: u64,
+}
+
+/// Storage used by the vault, `other` covers settings, shield ledgers and
+/// everything stored before storage was tracked
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StorageReport {
+    pub total: U64,
+    pub burns: U64,
+    pub committees: U64,
+    pub other: U64,
+    // yoctoNEAR locked for the total
+    pub staked: U128,
+}
+
+#[near_bindgen]
+impl Vault {
+    // getters
+
+    /// get storage used per subsystem and NEAR staked for it
+    pub fn get_storage_usage(&self) -> StorageReport {
+        let total = env::storage_usage();
+        let StorageUsage { burns, committees } = self.storage_usage;
+        StorageReport {
+            total: U64(total),
+            burns: U64(burns),
+            committees: U64(committees),
+            other: U64(total.saturating_sub(burns).saturating_sub(committees)),
+            staked: U128(total as u128 * env::storage_byte_cost()),
+        }
+    }
+}
+
+impl Vault {
+    /// track storage added or freed by `subsystem` since `before`, returns the bytes added
+    pub(crate) fn record_storage(&mut self, subsystem: Subsystem, before: u64) -> u64 {
+        let after = env::storage_usage();
+        let usage = match subsystem {
+            Subsystem::Burns => &mut self.storage_usage.burns,
+            Subsystem::Committees => &mut self.storage_usage.committees,
+            Subsystem::Other => return after.saturating_sub(before),
+        };
+        *usage = (*usage + after).saturating_sub(before);
+
+        after.saturating_sub(before)
+    }
+
+    /// check the attached deposit covers storage added since `before`
+    pub(crate) fn storage_covered(before: u64) -> bool {
+        let added = env::storage_usage().saturating_sub(before);
+        env::attached_deposit() >= added as u128 * env::storage_byte_cost()
+    }
+
+    /// charge storage added by `subsystem` since `before` to the attached deposit
+    /// and refund the rest to the caller
+    pub(crate) fn charge_storage(&mut self, subsystem: Subsystem, before: u64) {
+        assert!(Self::storage_covered(before), "{}", STORAGE_DEPOSIT_REQUIRED);
+        let added = self.record_storage(subsystem, before);
+        let cost = added as u128 * env::storage_byte_cost();
+        let deposit = env::attached_deposit();
+        if deposit > cost {
+            Promise::new(env::predecessor_account_id()).transfer(deposit - cost);
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use crate::utils::{DEFAULT_SHARD_ID, NFT_WITHDRAW_METADATA, SWAP_BEACON_METADATA};
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, PromiseResult, RuntimeFeesConfig, VMConfig};
+
+    fn setup() -> Vault {
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(0)).build());
+        let mut vault = Vault::new(test_utils::beacons(), 0);
+        vault.token_decimals.insert(&"token.near".to_string(), &9);
+        vault
+    }
+
+    #[test]
+    fn test_withdraw_storage_usage() {
+        let mut vault = setup();
+        let inst = test_utils::withdraw_inst("token.near", "alice.near", 100, [1; 32]);
+        let request = test_utils::shielded_request(&mut vault, &inst);
+        test_utils::attach_storage_deposit();
+        let before = env::storage_usage();
+        vault.withdraw(request, None);
+
+        let report = vault.get_storage_usage();
+        assert!(report.burns.0 > 0);
+        assert_eq!(report.burns.0, env::storage_usage() - before);
+        assert_eq!(report.committees, U64(0));
+        assert_eq!(report.total.0, report.burns.0 + report.other.0);
+        assert_eq!(report.staked, U128(report.total.0 as u128 * env::storage_byte_cost()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Attached deposit does not cover storage used by this call")]
+    fn test_withdraw_without_storage_deposit() {
+        let mut vault = setup();
+        let inst = test_utils::withdraw_inst("token.near", "alice.near", 100, [1; 32]);
+        let request = test_utils::shielded_request(&mut vault, &inst);
+        vault.withdraw(request, None);
+    }
+
+    #[test]
+    fn test_withdraw_nft_rollback_storage() {
+        let mut vault = setup();
+        vault.locked_nfts.insert(&(accounts(2).to_string(), "7".to_string()));
+        let inst = test_utils::withdraw_payload_inst(NFT_WITHDRAW_METADATA, accounts(2).as_str(), "alice.near", 0, [1; 32], "7");
+        let request = test_utils::verified_request(&mut vault, &inst);
+        test_utils::attach_storage_deposit();
+        let before = env::storage_usage();
+        vault.withdraw_nft(request, None);
+
+        testing_env!(
+            VMContextBuilder::new().storage_usage(env::storage_usage()).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed]
+        );
+        vault.on_withdraw_nft([1; 32], accounts(2), "7".to_string(), 0);
+        // the reverted burn record is all that is left
+        assert!(vault.storage_usage.burns > 0);
+        assert_eq!(vault.storage_usage.burns, env::storage_usage() - before);
+    }
+
+    #[test]
+    fn test_swap_storage_includes_verified_roots() {
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(0)).build());
+        let (secret_keys, keys) = test_utils::generated_keys(4);
+        let mut vault = Vault::new(keys, 0);
+        let mut inst = vec![SWAP_BEACON_METADATA, DEFAULT_SHARD_ID];
+        for value in [0u128, 10, 1] {
+            inst.extend_from_slice(&[0u8; 16]);
+            inst.extend_from_slice(&value.to_be_bytes());
+        }
+        inst.extend_from_slice(&[0u8; 12]);
+        inst.extend_from_slice(&[7u8; 20]);
+        let mut request = test_utils::inst_request(&inst);
+        test_utils::sign_request(&mut request, &secret_keys);
+
+        test_utils::attach_storage_deposit();
+        let before = env::storage_usage();
+        assert!(vault.swap_beacon_committee(request, None));
+        assert_eq!(vault.verified_roots.len(), 1);
+        assert_eq!(vault.storage_usage.committees, env::storage_usage() - before);
+    }
+
+    #[test]
+    fn test_record_freed_storage() {
+        let mut vault = setup();
+        vault.storage_usage.committees = 100;
+        let before = env::storage_usage();
+        vault.beacons.insert(&10, &Committee::from_hex(&test_utils::beacons()));
+        let added = vault.record_storage(Subsystem::Committees, before);
+        assert_eq!(vault.storage_usage.committees, 100 + added);
+
+        let before = env::storage_usage();
+        vault.beacons.remove(&10);
+        assert_eq!(vault.record_storage(Subsystem::Committees, before), 0);
+        assert_eq!(vault.storage_usage.committees, 100);
+    }
+}
@@ -0,0 +1,149 @@
+//! fixtures signed by the testnet beacon committee used in `test/shieldtests.js`
+
+use near_sdk::test_utils::VMContextBuilder;
+use near_sdk::{env, testing_env};
+
+use crate::root_cache::root_key;
+use crate::utils::{mt_token_key, MT_WITHDRAW_METADATA, WITHDRAW_FEE_METADATA, WITHDRAW_METADATA};
+use crate::{InteractRequest, Vault};
+
+pub(crate) fn to_32_bytes(hex_str: &str) -> [u8; 32] {
+    let bytes = hex::decode(hex_str).unwrap();
+    let mut bytes_ = [0u8; 32];
+    bytes_.copy_from_slice(&bytes);
+    bytes_
+}
+
+/// beacon public keys recovered from `signed_request` signatures
+pub(crate) fn beacons() -> Vec<String> {
+    vec![
+        "40cefd5438ce3fa29d989450c61742f52b01cfee0990a18b832c92884af216dcbb8291995d7275c76cbee9f435f0f730cf135ef50eabcf7c9db1ad8bfded24a8".to_string(),
+        "af6d7e12346c894e26fcd8d6e0d62cbb024346ccc44e9be0487e7c8086a5d29e8a5d3e5a4ce1ba27d7ccaa0a7f6356dc6bfb223aebec45bdebe239d06ad21c16".to_string(),
+        "7a45b364257511240004d37d96666ab4da7feec86854fab7171fd1e51675f84938787002bcbb982c46e419a0faff28d8b4efb7ebaf4f42297752c34667668749".to_string(),
+        "18ab0bad7650d53414ba4dd5b6f9bc460f25e481662db78b8bae932082b3a8ab244f1eed2c0bc8e56ce075e0ce0b3eeb7f65c274d1d57a294de5133cc7a85119".to_string(),
+    ]
+}
+
+/// request whose signatures are valid for `beacons` but whose instruction is not in the merkle tree
+pub(crate) fn signed_request() -> InteractRequest {
+    InteractRequest {
+        inst: "cuongcute".to_string(),
+        height: 16,
+        inst_paths: vec![to_32_bytes("23abf9d3acf3fde6246cce9e392c2154ab8423d8d2e01053e74db7f6d17aea4f")],
+        inst_path_is_lefts: vec![false],
+        inst_root: to_32_bytes("45e6d8d759bc5993097236e5f2d17053969f0b769bb1d0f8e222b6c40a0f6af3"),
+        blk_data: to_32_bytes("eff9f595401e37992a3a1fb0c1908e0d4bb2105eae42c0ef6499483b991f2c91"),
+        indexes: vec![0, 1, 2, 3],
+        signatures: vec![
+            "3ba689cfbcbfe81d10f47c0becd911ece7fd1c99ce3bf84c61cf20f3bfc2979438251b39a913e934bd6b61def19fac8da98808cce9b8f428809885364a49d81c".to_string(),
+            "fbb1705370519af0e89fa86ced533123a8a33db842a3d90a7c8c69ee82ce20c44ecf63c0f1646d7f2d173b7d4dae99c16e29af1bedcc5ee1a88e15c132f27136".to_string(),
+            "0cb23956deaaf8070c9dbc36e2035d1b641112d8b75187c7ee834f1dd00adf165c2a88fc3a356c795f6e4df4cf52c81f091d7a4fde215dba1eec47768da7b7ae".to_string(),
+            "6801dc29a7d1784f57c511369f84d68f04630bc7afcaa2b92c03272af26430fb7b93aaae22ce4f44818acb3345db276252ef71c7442cf1fe94d1d230191208cb".to_string(),
+        ],
+        vs: vec![0, 0, 1, 1],
+        signer_paths: vec![],
+    }
+}
+
+/// withdraw instruction of `amount` of `token` to `receiver`
+pub(crate) fn withdraw_inst(token: &str, receiver: &str, amount: u64, tx_id: [u8; 32]) -> Vec<u8> {
+    let mut inst = vec![WITHDRAW_METADATA, 1, token.len() as u8];
+    inst.extend_from_slice(&[0u8; 64][token.len()..]);
+    inst.extend_from_slice(token.as_bytes());
+    inst.push(receiver.len() as u8);
+    inst.extend_from_slice(&[0u8; 64][receiver.len()..]);
+    inst.extend_from_slice(receiver.as_bytes());
+    inst.extend_from_slice(&[0u8; 24]);
+    inst.extend_from_slice(&amount.to_be_bytes());
+    inst.extend_from_slice(&tx_id);
+    inst
+}
+
+/// withdraw call or memo instruction of type `meta` carrying `payload`
+pub(crate) fn withdraw_payload_inst(meta: u8, token: &str, receiver: &str, amount: u64, tx_id: [u8; 32], payload: &str) -> Vec<u8> {
+    let mut inst = withdraw_inst(token, receiver, amount, tx_id);
+    inst[0] = meta;
+    inst.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    inst.extend_from_slice(payload.as_bytes());
+    inst
+}
+
+/// withdraw fee instruction paying `fee` of `amount` to the relayer
+pub(crate) fn withdraw_fee_inst(token: &str, receiver: &str, amount: u64, tx_id: [u8; 32], fee: u64) -> Vec<u8> {
+    let mut inst = withdraw_inst(token, receiver, amount, tx_id);
+    inst[0] = WITHDRAW_FEE_METADATA;
+    inst.extend_from_slice(&fee.to_be_bytes());
+    inst
+}
+
+/// request for `inst` alone in its block, its signatures are for another block
+pub(crate) fn inst_request(inst: &[u8]) -> InteractRequest {
+    let mut leaf = inst.to_vec();
+    leaf.extend_from_slice(&[0u8; 16]);
+    leaf.extend_from_slice(&16u128.to_be_bytes());
+    let mut request = signed_request();
+    request.inst = hex::encode(inst);
+    request.inst_paths = vec![];
+    request.inst_path_is_lefts = vec![];
+    request.inst_root = env::keccak256_array(&leaf);
+    request
+}
+
+/// request for `inst` alone in its block, with the block root already verified by `vault`
+pub(crate) fn verified_request(vault: &mut Vault, inst: &[u8]) -> InteractRequest {
+    let request = inst_request(inst);
+    vault.verified_roots.insert(&root_key(&request, false, &vault.quorum));
+    request
+}
+
+/// secret and hex public keys of a generated committee of `size` members
+pub(crate) fn generated_keys(size: usize) -> (Vec<secp256k1::SecretKey>, Vec<String>) {
+    let secp = secp256k1::Secp256k1::new();
+    let secret_keys: Vec<secp256k1::SecretKey> = (1..=size as u8)
+        .map(|i| secp256k1::SecretKey::from_slice(&[i; 32]).unwrap())
+        .collect();
+    let keys = secret_keys.iter()
+        .map(|secret_key| hex::encode(&secp256k1::PublicKey::from_secret_key(&secp, secret_key).serialize_uncompressed()[1..]))
+        .collect();
+    (secret_keys, keys)
+}
+
+/// sign the block of `request` by the first members of a committee with `secret_keys`
+pub(crate) fn sign_request(request: &mut InteractRequest, secret_keys: &[secp256k1::SecretKey]) {
+    let secp = secp256k1::Secp256k1::new();
+    let mut blk_data = request.blk_data.to_vec();
+    blk_data.extend_from_slice(&request.inst_root);
+    let blk = env::keccak256_array(&env::keccak256(&blk_data));
+    let message = secp256k1::Message::from_slice(&blk).unwrap();
+    request.indexes = (0..secret_keys.len() as u8).collect();
+    request.signatures = vec![];
+    request.vs = vec![];
+    for secret_key in secret_keys {
+        let (v, signature) = secp.sign_ecdsa_recoverable(&message, secret_key).serialize_compact();
+        request.signatures.push(hex::encode(signature));
+        request.vs.push(v.to_i32() as u8);
+    }
+}
+
+/// `verified_request` of an unshield whose amount was shielded first, so its payout is within the locked ledger
+pub(crate) fn shielded_request(vault: &mut Vault, inst: &[u8]) -> InteractRequest {
+    if let Ok(decoded) = vault.strip_domain(inst.to_vec()).and_then(|inst| vault.decode_withdraw_inst(&inst)) {
+        let token = match (decoded.meta_type, &decoded.payload) {
+            (MT_WITHDRAW_METADATA, Some(token_id)) => mt_token_key(&decoded.token, token_id),
+            _ => decoded.token,
+        };
+        let amount = decoded.amount.saturating_mul(vault.credit_unit(&token));
+        vault.lock(&token, amount);
+    }
+    verified_request(vault, inst)
+}
+
+/// attach 1 NEAR to the next call to pay its storage, keeping the current caller and block
+pub(crate) fn attach_storage_deposit() {
+    testing_env!(VMContextBuilder::new()
+        .predecessor_account_id(env::predecessor_account_id())
+        .account_balance(env::account_balance())
+        .block_index(env::block_height())
+        .attached_deposit(10u128.pow(24))
+        .build());
+}
@@ -11,17 +11,27 @@ use crate::*;
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(crate = "near_sdk::serde")]
 #[serde(untagged)]
-enum TokenReceiverMessage {
+pub(crate) enum TokenReceiverMessage {
     Deposit {
         incognito_address: String
     },
 }
 
+/// incognito address a deposit `msg` shields to, see `TokenReceiverMessage`
+pub(crate) fn parse_deposit_msg(msg: &str) -> String {
+    if msg.is_empty() {
+        panic!("{}", INVALID_MESSAGE)
+    }
+    let TokenReceiverMessage::Deposit { incognito_address } =
+        serde_json::from_str::<TokenReceiverMessage>(msg).expect(ERR28_WRONG_MSG_FORMAT);
+    incognito_address
+}
+
 #[near_bindgen]
 impl FungibleTokenReceiver for Vault {
     /// Callback on receiving tokens by this contract.
     /// `msg` format is either "" for deposit or `TokenReceiverMessage`.
-    #[allow(unreachable_code)]
+    #[allow(unreachable_code, unused_variables)]
     fn ft_on_transfer(
         &mut self,
         sender_id: AccountId,
@@ -29,38 +39,23 @@ impl FungibleTokenReceiver for Vault {
         msg: String,
     ) -> PromiseOrValue<U128> {
         let token_in = env::predecessor_account_id();
-        if msg.is_empty() {
-            panic!("{}", INVALID_MESSAGE)
-        }
         // shield request
-        let message =
-            serde_json::from_str::<TokenReceiverMessage>(&msg).expect(ERR28_WRONG_MSG_FORMAT);
-        match message {
-            TokenReceiverMessage::Deposit {
-                incognito_address
-            } => {
-                let amount = amount.0;
-                ext_ft::ft_metadata(
-                    token_in.clone(),
-                    0,
-                    Gas(5_000_000_000_000),          // gas to attach
-                )
-                .and(ext_ft::ft_balance_of(
-                    env::current_account_id().clone(),
-                    token_in.clone(),
-                    0,
-                    Gas(5_000_000_000_000),         // gas to attach
-                ))
-                .then(ext_self::fallback_deposit(
+        let incognito_address = parse_deposit_msg(&msg);
+        let amount = amount.0;
+        self.add_pending(token_in.as_str(), amount);
+        ext_ft::ext(token_in.clone())
+            .with_static_gas(Gas(5_000_000_000_000))      // gas to attach
+            .ft_metadata()
+            .and(ext_ft::ext(token_in.clone())
+                .with_static_gas(Gas(5_000_000_000_000))  // gas to attach
+                .ft_balance_of(env::current_account_id()))
+            .then(ext_self::ext(env::current_account_id())
+                .with_static_gas(Gas(5_000_000_000_000))  // gas to attach to the callback
+                .fallback_deposit(
                     incognito_address,
                     token_in,
                     amount,
-                    env::current_account_id().clone(),
-                    0,
-                    Gas(5_000_000_000_000),          // gas to attach to the callback
                 )).into()
-            }
-        }
     }
 }
 
@@ -69,6 +64,62 @@ impl FungibleTokenReceiver for Vault {
 #[cfg(all(test, not(target_arch = "wasm32")))]
 mod tests {
     use super::*;
+    use crate::test_utils;
+    use near_sdk::test_utils::{accounts, get_logs, VMContextBuilder};
+    use near_sdk::{testing_env, PromiseResult, RuntimeFeesConfig, VMConfig};
+
+    fn setup() -> Vault {
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(0)).build());
+        Vault::new(test_utils::beacons(), 0)
+    }
+
+    /// callback context of `ft_on_transfer` with the metadata and balance results
+    fn with_results(metadata: PromiseResult, balance: PromiseResult) {
+        testing_env!(
+            VMContextBuilder::new().current_account_id(accounts(0)).predecessor_account_id(accounts(0)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![metadata, balance]
+        );
+    }
+
+    fn metadata() -> PromiseResult {
+        PromiseResult::Successful(
+            br#"{"spec":"ft-1.0.0","name":"Token","symbol":"TKN","icon":null,"reference":null,"reference_hash":null,"decimals":9}"#.to_vec()
+        )
+    }
+
+    fn unused(result: PromiseOrValue<U128>) -> U128 {
+        match result {
+            PromiseOrValue::Value(unused) => unused,
+            PromiseOrValue::Promise(_) => panic!("deposit callback returned a promise"),
+        }
+    }
+
+    #[test]
+    fn test_fallback_deposit() {
+        let mut vault = setup();
+        with_results(metadata(), PromiseResult::Successful(b"\"100\"".to_vec()));
+        let result = vault.fallback_deposit("incognito-address".to_string(), accounts(2), 100);
+        assert_eq!(unused(result), U128(0));
+        assert_eq!(vault.get_locked_balance(accounts(2).to_string()), U128(100));
+        assert_eq!(get_logs(), vec![format!("incognito-address {} 100", accounts(2))]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the vault can call this method")]
+    fn test_fallback_deposit_foreign_predecessor() {
+        let mut vault = setup();
+        testing_env!(
+            VMContextBuilder::new().current_account_id(accounts(0)).predecessor_account_id(accounts(3)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![metadata(), PromiseResult::Successful(b"\"100\"".to_vec())]
+        );
+        vault.fallback_deposit("incognito-address".to_string(), accounts(2), 100);
+    }
 
     #[test]
     fn test_serialize() {
@@ -79,10 +130,39 @@ mod tests {
         println!("{}", msg_str);
     }
 
+    #[test]
+    #[should_panic(expected = "Message in FT transfer must not be empty")]
+    fn test_parse_empty_deposit_msg() {
+        parse_deposit_msg("");
+    }
+
+    #[test]
+    fn test_fallback_deposit_metadata_failed() {
+        let mut vault = setup();
+        vault.add_pending(accounts(2).as_str(), 100);
+        with_results(PromiseResult::Failed, PromiseResult::Successful(b"\"100\"".to_vec()));
+        let result = vault.fallback_deposit("incognito-address".to_string(), accounts(2), 100);
+        assert_eq!(unused(result), U128(100));
+        assert_eq!(vault.pending_deposits.get(&accounts(2).to_string()), Some(0));
+        assert_eq!(vault.get_locked_balance(accounts(2).to_string()), U128(0));
+    }
+
+    #[test]
+    fn test_fallback_deposit_cap_exceeded() {
+        let mut vault = setup();
+        vault.set_deposit_cap(accounts(2).to_string(), Some(U128(99)));
+        vault.add_pending(accounts(2).as_str(), 100);
+        with_results(metadata(), PromiseResult::Successful(b"\"100\"".to_vec()));
+        let result = vault.fallback_deposit("incognito-address".to_string(), accounts(2), 100);
+        assert_eq!(unused(result), U128(100));
+        assert_eq!(vault.pending_deposits.get(&accounts(2).to_string()), Some(0));
+        assert_eq!(get_logs(), vec![format!("refund {} 100: {}", accounts(2), VALUE_EXCEEDED)]);
+    }
+
     #[test]
     fn test_deserialize() {
         let msg_str = r#"{"incognito_address":"my_address"}"#;
-        let msg_obj: TokenReceiverMessage = serde_json::from_str(&msg_str).unwrap();
+        let msg_obj: TokenReceiverMessage = serde_json::from_str(msg_str).unwrap();
         println!("{:?}", msg_obj);
     }
 }
\ No newline at end of file
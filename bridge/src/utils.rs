@@ -1,63 +1,239 @@
 use crate::{errors::*, InteractRequest};
-use near_sdk::{env};
+use crate::committee::{Committee, Quorum, BEACON_ADDRESS_LEN};
+use near_sdk::{env, Promise, PromiseResult};
+use std::convert::TryInto;
+use arrayref::{array_refs, array_ref};
 
 pub const WITHDRAW_METADATA: u8 = 157;
 pub const SWAP_BEACON_METADATA: u8 = 158;
+pub const SWAP_BRIDGE_METADATA: u8 = 159;
 pub const BURN_METADATA: u8 = 160;
+pub const SET_QUORUM_METADATA: u8 = 161;
+// withdraw delivering the token with `ft_transfer_call` and the instruction message
+pub const WITHDRAW_CALL_METADATA: u8 = 162;
+// withdraw forwarding its memo to `ft_transfer`, logged for native NEAR
+pub const WITHDRAW_MEMO_METADATA: u8 = 163;
+// withdraw paying its relayer fee to the caller out of the amount
+pub const WITHDRAW_FEE_METADATA: u8 = 164;
+pub const NFT_WITHDRAW_METADATA: u8 = 165;
+// withdraw releasing a NEP-245 token id with `mt_transfer`
+pub const MT_WITHDRAW_METADATA: u8 = 166;
+// withdraw whose amount is read from all of its 32 bytes
+pub const WITHDRAW_WIDE_METADATA: u8 = 167;
+
+// shard id Incognito emits bridge instructions from unless configured otherwise
+pub const DEFAULT_SHARD_ID: u8 = 1;
 
 pub const NEAR_ADDRESS: &str = "0000000000000000000000000000000000000001";
-pub const WITHDRAW_INST_LEN: usize = 1 + 1 + 1 + 64 + 1 + 64 + 32 + 32; // ignore last 64 bytes in instruction
+// versioned instructions are prefixed with this byte and the vault domain
+pub const DOMAIN_INST_VERSION: u8 = 2;
+pub const DOMAIN_HEADER_LEN: usize = 1 + 32;
+// withdraw call, memo and nft instructions append 2 bytes length then the utf8 message, memo or token id
+pub const WITHDRAW_PAYLOAD_LEN: usize = 2;
+// withdraw fee instruction appends the 8 bytes relayer fee, in the unit of the amount
+pub const WITHDRAW_FEE_LEN: usize = 8;
 pub const SWAP_COMMITTEE_INST_LEN: usize = 1 + 1 + 32 + 32 + 32;
+pub const SET_QUORUM_INST_LEN: usize = 1 + 1 + 8 + 8 + 8;
+// beacon heights a superseded committee is kept for before it can be pruned
+pub const DEFAULT_COMMITTEE_RETENTION: u128 = 1_000_000;
+// deposit cap of tokens without one set, in Incognito's 9 decimals
+pub const DEFAULT_DEPOSIT_CAP: u128 = u64::MAX as u128;
+// burn records scanned by one history page
+pub const MAX_BURN_PAGE: u64 = 100;
+// tx ids checked by one `are_burns_processed` call
+pub const MAX_BURN_QUERY: usize = 100;
+// highest protocol fee in basis points
+pub const MAX_FEE_BPS: u16 = 1_000;
+// signature verified block roots kept at once
+pub const VERIFIED_ROOT_CAPACITY: u64 = 256;
+// NEAR blocks a verified block root stays usable
+pub const VERIFIED_ROOT_TTL: u64 = 43_200;
 
+/// verify signatures and inclusion without the root cache
+#[cfg(all(test, not(target_arch = "wasm32")))]
 pub fn verify_inst(
-    request_info: &InteractRequest, beacons: Vec<String>,
+    request_info: &InteractRequest, beacons: Committee, quorum: &Quorum,
 ) {
+    verify_signatures(request_info, &beacons, quorum)
+        .and_then(|_| verify_merkle(request_info))
+        .unwrap_or_else(|err| panic!("{}", err));
+}
+
+/// check instruction is committed by `inst_root`
+pub fn verify_merkle(request_info: &InteractRequest) -> Result<(), &'static str> {
+    // append block height to instruction
+    let height_vec = append_at_top(request_info.height);
+    let mut inst_vec = hex::decode(&request_info.inst).unwrap_or_default();
+    inst_vec.extend_from_slice(&height_vec);
+    let inst_hash = env::keccak256_array(inst_vec.as_slice());
+    if !instruction_in_merkle_tree(
+        &inst_hash,
+        &request_info.inst_root,
+        &request_info.inst_paths,
+        &request_info.inst_path_is_lefts
+    ) {
+        return Err(INVALID_MERKLE_TREE);
+    }
+
+    Ok(())
+}
+
+/// check quorum of `beacons` signed the block committing to `inst_root`
+///
+/// every signature is recovered with ecrecover, the only signature scheme exposed to contracts
+/// by this sdk, so merkle root committees save committee storage rather than verification gas
+pub fn verify_signatures(
+    request_info: &InteractRequest, beacons: &Committee, quorum: &Quorum,
+) -> Result<(), &'static str> {
     if request_info.indexes.len() != request_info.signatures.len()
         || request_info.signatures.len() != request_info.vs.len()
     {
-        panic!("{}", INVALID_KEY_AND_INDEX);
+        return Err(INVALID_KEY_AND_INDEX);
     }
 
-    if beacons.len().eq(&0) {
-        panic!("{}", INVALID_BEACON_LIST);
+    if beacons.is_empty() {
+        return Err(INVALID_BEACON_LIST);
     }
-    if request_info.signatures.len() <= beacons.len() * 2 / 3 {
-        panic!("{}", INVALID_NUMBER_OF_SIGS);
+    if !quorum.is_reached(request_info.signatures.len(), beacons.len()) {
+        return Err(INVALID_NUMBER_OF_SIGS);
+    }
+    // each beacon counts once towards quorum
+    let mut signers = request_info.indexes.clone();
+    signers.sort_unstable();
+    signers.dedup();
+    if signers.len() != request_info.indexes.len() {
+        return Err(DUPLICATE_BEACON_SIGNATURE);
     }
 
-    let mut blk_data_bytes = request_info.blk_data.to_vec();
-        blk_data_bytes.extend_from_slice(&request_info.inst_root);
-        // Get double block hash from instRoot and other data
-        let blk = env::keccak256_array(env::keccak256(blk_data_bytes.as_slice()).as_slice());
-
-        // verify beacon signature
-        for i in 0..request_info.indexes.len() {
-            let (s_r, v) = (hex::decode(request_info.signatures[i].clone()).unwrap_or_default(), request_info.vs[i]);
-            let index_beacon = request_info.indexes[i];
-            let beacon_key = beacons[index_beacon as usize].clone();
-            let recover_key = env::ecrecover(
-                &blk,
-                s_r.as_slice(),
-                v,
-                false,
-            ).unwrap();
-            if !hex::encode(recover_key).eq(beacon_key.as_str()) {
-                panic!("{}", INVALID_BEACON_SIGNATURE);
-            }
+    if let Committee::MerkleRoot { .. } = beacons {
+        if request_info.signer_paths.len() != request_info.indexes.len() {
+            return Err(MISSING_SIGNER_PATHS);
         }
-        // append block height to instruction
-        let height_vec = append_at_top(request_info.height);
-        let mut inst_vec = hex::decode(&request_info.inst).unwrap_or_default();
-        inst_vec.extend_from_slice(&height_vec);
-        let inst_hash = env::keccak256_array(inst_vec.as_slice());
-        if !instruction_in_merkle_tree(
-            &inst_hash,
-            &request_info.inst_root,
-            &request_info.inst_paths,
-            &request_info.inst_path_is_lefts
-        ) {
-            panic!("{}", INVALID_MERKLE_TREE);
+    }
+
+    let mut blk_data_bytes = request_info.blk_data.to_vec();
+    blk_data_bytes.extend_from_slice(&request_info.inst_root);
+    // Get double block hash from instRoot and other data
+    let blk = env::keccak256_array(env::keccak256(blk_data_bytes.as_slice()).as_slice());
+
+    // verify beacon signature
+    for i in 0..request_info.indexes.len() {
+        let (s_r, v) = (hex::decode(request_info.signatures[i].clone()).unwrap_or_default(), request_info.vs[i]);
+        let index_beacon = request_info.indexes[i];
+        let recover_key = env::ecrecover(
+            &blk,
+            s_r.as_slice(),
+            v,
+            false,
+        ).ok_or(INVALID_BEACON_SIGNATURE)?;
+        let path = request_info.signer_paths.get(i).map(|path| path.as_slice()).unwrap_or_default();
+        if !beacons.is_signer(index_beacon as usize, &recover_key, path) {
+            return Err(INVALID_BEACON_SIGNATURE);
         }
+    }
+
+    Ok(())
+}
+
+/// parse swap committee instruction of type `meta` from one of `shard_ids`
+///
+/// returns previous committee height, new committee height and new committee
+pub fn parse_swap_inst(inst: &[u8], meta: u8, shard_ids: &[u8]) -> (u128, u128, Committee) {
+    let inst_ = array_ref![inst, 0, SWAP_COMMITTEE_INST_LEN];
+    #[allow(clippy::ptr_offset_with_cast)]
+    let (meta_type, shard_id, _, prev_height, _, height, _, num_vals) =
+        array_refs![inst_, 1, 1, 16, 16, 16, 16, 16, 16];
+    let meta_type = u8::from_be_bytes(*meta_type);
+    let shard_id = u8::from_be_bytes(*shard_id);
+    let prev_height = u128::from_be_bytes(*prev_height);
+    let height = u128::from_be_bytes(*height);
+    let num_vals = u128::from_be_bytes(*num_vals);
+
+    // each member is a 32 bytes word holding its address in the last 20 bytes
+    let mut members: Vec<[u8; 20]> = vec![];
+    for i in 0..num_vals {
+        let index = i as usize;
+        let member = array_ref![inst, SWAP_COMMITTEE_INST_LEN + index * 32 + 32 - BEACON_ADDRESS_LEN, BEACON_ADDRESS_LEN];
+        members.push(*member);
+    }
+
+    // validate metatype and key provided
+    if meta_type != meta || !shard_ids.contains(&shard_id) {
+        panic!("{}", INVALID_METADATA);
+    }
+
+    (prev_height, height, Committee::Addresses(members))
+}
+
+/// message, memo or token id appended at `offset` of withdraw instruction, none when malformed
+pub fn parse_withdraw_payload(inst: &[u8], offset: usize) -> Option<String> {
+    let payload = inst.get(offset..)?;
+    let payload_len = u16::from_be_bytes(*array_ref![payload.get(..WITHDRAW_PAYLOAD_LEN)?, 0, WITHDRAW_PAYLOAD_LEN]) as usize;
+    let payload = payload.get(WITHDRAW_PAYLOAD_LEN..WITHDRAW_PAYLOAD_LEN + payload_len)?;
+    String::from_utf8(payload.to_vec()).ok()
+}
+
+/// burn tx id given as 64 hex characters or base58
+pub fn parse_tx_id(tx_id: &str) -> Option<[u8; 32]> {
+    let bytes = if tx_id.len() == 64 {
+        hex::decode(tx_id).ok()?
+    } else {
+        bs58::decode(tx_id).into_vec().ok()?
+    };
+    bytes.try_into().ok()
+}
+
+/// units of a token with `decimals` per unit of Incognito's 9 decimals
+pub fn incognito_unit(decimals: u8) -> u128 {
+    if decimals > 9 { u128::pow(10, decimals as u32 - 9) } else { 1 }
+}
+
+/// key of a NEP-245 token id in per-token maps, account ids never contain ':'
+pub fn mt_token_key(contract: &str, token_id: &str) -> String {
+    format!("{}:{}", contract, token_id)
+}
+
+/// contract and token id of a `mt_token_key`, none for other tokens
+pub fn split_mt_token_key(key: &str) -> Option<(&str, &str)> {
+    key.split_once(':')
+}
+
+/// whether the promise this callback waits on at `index` succeeded
+pub fn promise_succeeded(index: u64) -> bool {
+    matches!(env::promise_result(index), PromiseResult::Successful(_))
+}
+
+/// run `promise` once `prior` resolved, `promise` must not be chained yet
+pub fn then_after(prior: Option<Promise>, promise: Promise) -> Promise {
+    match prior {
+        Some(prior) => prior.then(promise),
+        None => promise,
+    }
+}
+
+/// relayer fee appended at `offset` of withdraw fee instruction, none when malformed
+pub fn parse_relayer_fee(inst: &[u8], offset: usize) -> Option<u128> {
+    let fee = inst.get(offset..offset + WITHDRAW_FEE_LEN)?;
+    Some(u128::from(u64::from_be_bytes(*array_ref![fee, 0, WITHDRAW_FEE_LEN])))
+}
+
+/// parse set quorum instruction from one of `shard_ids`
+///
+/// returns numerator, denominator and minimum signers of the new quorum
+pub fn parse_quorum_inst(inst: &[u8], shard_ids: &[u8]) -> (u64, u64, u64) {
+    let inst_ = array_ref![inst, 0, SET_QUORUM_INST_LEN];
+    #[allow(clippy::ptr_offset_with_cast)]
+    let (meta_type, shard_id, numerator, denominator, min_signers) =
+        array_refs![inst_, 1, 1, 8, 8, 8];
+    let meta_type = u8::from_be_bytes(*meta_type);
+    let shard_id = u8::from_be_bytes(*shard_id);
+
+    // validate metatype and key provided
+    if meta_type != SET_QUORUM_METADATA || !shard_ids.contains(&shard_id) {
+        panic!("{}", INVALID_METADATA);
+    }
+
+    (u64::from_be_bytes(*numerator), u64::from_be_bytes(*denominator), u64::from_be_bytes(*min_signers))
 }
 
 fn append_at_top(input: u128) -> Vec<u8>  {
@@ -72,13 +248,13 @@ fn append_at_top(input: u128) -> Vec<u8>  {
 fn instruction_in_merkle_tree(
     leaf: &[u8; 32],
     root: &[u8; 32],
-    paths: &Vec<[u8; 32]>,
-    path_lefts: &Vec<bool>
+    paths: &[[u8; 32]],
+    path_lefts: &[bool]
 ) -> bool {
     if paths.len() != path_lefts.len() {
         return false;
     }
-    let mut build_root = leaf.clone();
+    let mut build_root = *leaf;
     let mut temp;
     for i in 0..paths.len() {
         if path_lefts[i] {
@@ -94,4 +270,112 @@ fn instruction_in_merkle_tree(
         build_root = env::keccak256_array(&temp[..]);
     }
     build_root == *root
-}
\ No newline at end of file
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::committee::to_address;
+    use crate::inst::LEGACY_WITHDRAW_LAYOUT;
+    use crate::test_utils::{beacons, signed_request, withdraw_inst, withdraw_payload_inst};
+
+    #[test]
+    #[should_panic(expected = "merkle tree root is not match")]
+    fn test_verify_signatures() {
+        verify_inst(&signed_request(), Committee::from_hex(&beacons()), &Quorum::default());
+    }
+
+    #[test]
+    #[should_panic(expected = "merkle tree root is not match")]
+    fn test_verify_three_of_four() {
+        let mut request = signed_request();
+        request.indexes.pop();
+        request.signatures.pop();
+        request.vs.pop();
+        verify_inst(&request, Committee::from_hex(&beacons()), &Quorum::default());
+    }
+
+    #[test]
+    #[should_panic(expected = "The total signature must reach majority of beacon list")]
+    fn test_verify_two_of_four() {
+        let mut request = signed_request();
+        request.indexes.truncate(2);
+        request.signatures.truncate(2);
+        request.vs.truncate(2);
+        verify_inst(&request, Committee::from_hex(&beacons()), &Quorum::default());
+    }
+
+    #[test]
+    #[should_panic(expected = "Duplicate beacon signature index")]
+    fn test_verify_duplicate_signer() {
+        let mut request = signed_request();
+        request.indexes[3] = 0;
+        request.signatures[3] = request.signatures[0].clone();
+        request.vs[3] = request.vs[0];
+        verify_inst(&request, Committee::from_hex(&beacons()), &Quorum::default());
+    }
+
+    #[test]
+    fn test_parse_withdraw_payload() {
+        let inst = withdraw_payload_inst(WITHDRAW_CALL_METADATA, "token.near", "dapp.near", 5, [1; 32], "{\"action\":\"stake\"}");
+        assert_eq!(parse_withdraw_payload(&inst, LEGACY_WITHDRAW_LAYOUT.len()), Some("{\"action\":\"stake\"}".to_string()));
+        assert_eq!(parse_withdraw_payload(&inst[..inst.len() - 1], LEGACY_WITHDRAW_LAYOUT.len()), None);
+        assert_eq!(parse_withdraw_payload(&withdraw_inst("token.near", "dapp.near", 5, [1; 32]), LEGACY_WITHDRAW_LAYOUT.len()), None);
+    }
+
+    #[test]
+    fn test_parse_tx_id() {
+        let tx_id = [7u8; 32];
+        assert_eq!(parse_tx_id(&hex::encode(tx_id)), Some(tx_id));
+        assert_eq!(parse_tx_id(&bs58::encode(tx_id).into_string()), Some(tx_id));
+        assert_eq!(parse_tx_id(&hex::encode([7u8; 31])), None);
+        assert_eq!(parse_tx_id("0OIl"), None);
+    }
+
+    #[test]
+    fn test_parse_quorum_inst() {
+        let mut inst = vec![SET_QUORUM_METADATA, 1];
+        inst.extend_from_slice(&3u64.to_be_bytes());
+        inst.extend_from_slice(&4u64.to_be_bytes());
+        inst.extend_from_slice(&5u64.to_be_bytes());
+        assert_eq!(parse_quorum_inst(&inst, &[DEFAULT_SHARD_ID]), (3, 4, 5));
+    }
+
+    #[test]
+    #[should_panic(expected = "merkle tree root is not match")]
+    fn test_verify_signatures_by_address() {
+        let addresses: Vec<String> = beacons().iter()
+            .map(|key| {
+                let key = hex::decode(key).unwrap();
+                hex::encode(to_address(array_ref![key, 0, 64]))
+            })
+            .collect();
+        verify_inst(&signed_request(), Committee::from_hex(&addresses), &Quorum::default());
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid beacon signature")]
+    fn test_verify_wrong_signer() {
+        let mut request = signed_request();
+        request.indexes = vec![1, 0, 2, 3];
+        verify_inst(&request, Committee::from_hex(&beacons()), &Quorum::default());
+    }
+
+    #[test]
+    fn test_parse_swap_inst() {
+        let mut inst = vec![SWAP_BRIDGE_METADATA, 1];
+        inst.extend_from_slice(&[0u8; 16]);
+        inst.extend_from_slice(&5u128.to_be_bytes());
+        inst.extend_from_slice(&[0u8; 16]);
+        inst.extend_from_slice(&9u128.to_be_bytes());
+        inst.extend_from_slice(&[0u8; 16]);
+        inst.extend_from_slice(&2u128.to_be_bytes());
+        for member in [[1u8; 20], [2u8; 20]] {
+            inst.extend_from_slice(&[0u8; 12]);
+            inst.extend_from_slice(&member);
+        }
+        let (prev_height, height, committee) = parse_swap_inst(&inst, SWAP_BRIDGE_METADATA, &[DEFAULT_SHARD_ID]);
+        assert_eq!((prev_height, height), (5, 9));
+        assert_eq!(committee, Committee::Addresses(vec![[1u8; 20], [2u8; 20]]));
+    }
+}
@@ -0,0 +1,292 @@
+use near_sdk::{env, near_bindgen, AccountId, Gas, Promise};
+use near_sdk::json_types::U128;
+
+use crate::*;
+
+// wNEAR and NEAR both have 24 decimals
+const WRAP_NEAR_DECIMALS: u8 = 24;
+
+#[near_bindgen]
+impl Vault {
+    // getters
+
+    /// get wNEAR contract native deposits are wrapped into, none when kept native
+    pub fn get_wrap_near(&self) -> Option<AccountId> {
+        self.wrap_near.clone()
+    }
+
+    /// check account receives wNEAR unshields as native NEAR
+    pub fn get_unwrap_near(&self, account_id: AccountId) -> bool {
+        self.unwrap_near_receivers.contains(&account_id)
+    }
+
+    // setters
+
+    /// wrap native deposits into `wrap_near` from now on, none to keep them native
+    ///
+    /// the vault must be registered with the wNEAR contract
+    pub fn set_wrap_near(&mut self, wrap_near: Option<AccountId>) {
+        self.assert_admin();
+        if let Some(wrap_near) = &wrap_near {
+            self.token_decimals.insert(&wrap_near.to_string(), &WRAP_NEAR_DECIMALS);
+        }
+        self.wrap_near = wrap_near;
+    }
+
+    /// request wNEAR unshields to the caller to be unwrapped to native NEAR,
+    /// the attached deposit pays the storage used
+    #[payable]
+    pub fn set_unwrap_near(&mut self, unwrap: bool) {
+        let storage_before = env::storage_usage();
+        let account = env::predecessor_account_id();
+        if unwrap {
+            self.unwrap_near_receivers.insert(&account);
+        } else {
+            self.unwrap_near_receivers.remove(&account);
+        }
+        self.charge_storage(Subsystem::Other, storage_before);
+    }
+
+    /// emit shield of deposit wrapped into `wrap_near`, refund the sender when wrapping failed
+    #[private]
+    pub fn on_wrap_deposit(&mut self, incognito_address: String, sender: AccountId, wrap_near: AccountId, amount: U128) {
+        self.remove_pending(wrap_near.as_str(), amount.0);
+        if !promise_succeeded(0) {
+            Promise::new(sender).transfer(amount.0);
+            return;
+        }
+
+        self.emit_shield(&incognito_address, wrap_near.as_str(), amount.0, WRAP_NEAR_DECIMALS);
+    }
+
+    /// send unwrapped NEAR to receiver, or keep the wNEAR claimable when unwrapping failed.
+    /// returns whether the NEAR was sent
+    #[private]
+    pub fn on_unwrap_near(&mut self, receiver: AccountId, token: AccountId, amount: U128) -> bool {
+        self.remove_withdrawing(token.as_str(), amount.0);
+        if promise_succeeded(0) {
+            Promise::new(receiver).transfer(amount.0);
+            return true;
+        }
+        self.add_claimable(&receiver, token.as_str(), amount.0);
+        false
+    }
+}
+
+impl Vault {
+    /// wrap attached deposit, the shield is emitted once wNEAR is minted
+    pub(crate) fn wrap_deposit(&mut self, wrap_near: AccountId, incognito_address: String) -> Promise {
+        let amount = env::attached_deposit();
+        // the cap bounds wNEAR held for shields, deposits still being wrapped included
+        let [locked, pending, ..] = self.ledgers(wrap_near.as_str());
+        let balance = locked.saturating_add(pending).saturating_add(amount);
+        self.assert_deposit_cap(wrap_near.as_str(), balance / incognito_unit(WRAP_NEAR_DECIMALS));
+        self.add_pending(wrap_near.as_str(), amount);
+        ext_wnear::ext(wrap_near.clone())
+            .with_attached_deposit(amount)
+            .with_static_gas(Gas(5_000_000_000_000))
+            .near_deposit()
+            .then(ext_self::ext(env::current_account_id())
+                .with_static_gas(Gas(10_000_000_000_000))
+                .on_wrap_deposit(
+                    incognito_address,
+                    env::predecessor_account_id(),
+                    wrap_near,
+                    U128(amount),
+                ))
+    }
+
+    /// whether `token` unshielded to `receiver` is paid out as native NEAR
+    pub(crate) fn is_unwrapped(&self, token: &AccountId, receiver: &AccountId) -> bool {
+        self.wrap_near.as_ref() == Some(token) && self.unwrap_near_receivers.contains(receiver)
+    }
+
+    /// unwrap wNEAR then transfer it natively to receiver
+    pub(crate) fn unwrap_transfer(&self, prior: Option<Promise>, token: AccountId, receiver: AccountId, amount: u128) -> Promise {
+        then_after(prior, ext_wnear::ext(token.clone())
+            .with_attached_deposit(1)
+            .with_static_gas(Gas(5_000_000_000_000))
+            .near_withdraw(
+                U128(amount),
+            ))
+            .then(ext_self::ext(env::current_account_id())
+                .with_static_gas(Gas(10_000_000_000_000))
+                .on_unwrap_near(
+                    receiver,
+                    token,
+                    U128(amount),
+                ))
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use near_sdk::test_utils::{accounts, get_logs, VMContextBuilder};
+    use near_sdk::{serde_json, testing_env, RuntimeFeesConfig, VMConfig};
+
+    fn wnear() -> AccountId {
+        "wrap.near".parse().unwrap()
+    }
+
+    fn setup() -> Vault {
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(0)).build());
+        let mut vault = Vault::new(vec!["00".repeat(64)], 10);
+        vault.set_wrap_near(Some(wnear()));
+        vault
+    }
+
+    fn with_promise_result(result: PromiseResult) {
+        testing_env!(
+            VMContextBuilder::new().predecessor_account_id(accounts(1)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![result]
+        );
+    }
+
+    #[test]
+    fn test_wrap_deposit() {
+        let mut vault = setup();
+        assert_eq!(vault.token_decimals.get(&wnear().to_string()), Some(24));
+        testing_env!(VMContextBuilder::new().attached_deposit(10u128.pow(24)).build());
+        assert!(matches!(vault.deposit("incognito-address".to_string()), PromiseOrValue::Promise(_)));
+        // shield is only emitted once wrapped
+        assert!(get_logs().is_empty());
+
+        with_promise_result(PromiseResult::Successful(vec![]));
+        vault.on_wrap_deposit("incognito-address".to_string(), accounts(1), wnear(), U128(10u128.pow(24)));
+        assert_eq!(get_logs(), vec!["incognito-address wrap.near 1000000000".to_string()]);
+    }
+
+    #[test]
+    #[should_panic(expected = "the total balance greater than max value allowed to shield")]
+    fn test_wrap_deposit_cap() {
+        let mut vault = setup();
+        vault.set_deposit_cap(wnear().to_string(), Some(U128(1_500_000_000)));
+        // the vault's own NEAR does not count against the wNEAR cap
+        testing_env!(VMContextBuilder::new().account_balance(10u128.pow(27)).attached_deposit(10u128.pow(24)).build());
+        vault.deposit("incognito-address".to_string());
+        assert_eq!(vault.pending_deposits.get(&wnear().to_string()), Some(10u128.pow(24)));
+        // the first deposit is still being wrapped
+        vault.deposit("incognito-address".to_string());
+    }
+
+    #[test]
+    fn test_wrap_deposit_after_disabled() {
+        let mut vault = setup();
+        vault.set_wrap_near(None);
+        // a deposit wrapped before wrapping was disabled is still shielded as wNEAR
+        with_promise_result(PromiseResult::Successful(vec![]));
+        vault.on_wrap_deposit("incognito-address".to_string(), accounts(1), wnear(), U128(10u128.pow(24)));
+        assert_eq!(get_logs(), vec!["incognito-address wrap.near 1000000000".to_string()]);
+    }
+
+    #[test]
+    fn test_wrap_deposit_failed() {
+        let mut vault = setup();
+        with_promise_result(PromiseResult::Failed);
+        vault.on_wrap_deposit("incognito-address".to_string(), accounts(1), wnear(), U128(10u128.pow(24)));
+        assert!(get_logs().is_empty());
+    }
+
+    #[test]
+    fn test_unwrap_near() {
+        let mut vault = setup();
+        assert!(!vault.is_unwrapped(&wnear(), &accounts(1)));
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).build());
+        test_utils::attach_storage_deposit();
+        vault.set_unwrap_near(true);
+        assert!(vault.is_unwrapped(&wnear(), &accounts(1)));
+        assert!(!vault.is_unwrapped(&accounts(2), &accounts(1)));
+
+        // failed unwrap keeps the wNEAR claimable
+        with_promise_result(PromiseResult::Failed);
+        vault.on_unwrap_near(accounts(1), wnear(), U128(5));
+        assert_eq!(vault.get_claimable(accounts(1), wnear().to_string()), U128(5));
+    }
+
+    #[test]
+    fn test_withdraw_relayer_fee_unwrapped() {
+        let mut vault = setup();
+        vault.token_decimals.insert(&wnear().to_string(), &24);
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).build());
+        test_utils::attach_storage_deposit();
+        vault.set_unwrap_near(true);
+        // fee transfer, its callback, then the unwrap and its callback in one chain
+        let inst = test_utils::withdraw_fee_inst(wnear().as_str(), accounts(1).as_str(), 5, [1; 32], 2);
+        let request = test_utils::shielded_request(&mut vault, &inst);
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(3)).build());
+        test_utils::attach_storage_deposit();
+        vault.withdraw(request, None);
+        assert!(vault.tx_burn.get(&[1; 32]).unwrap_or_default());
+    }
+
+    #[test]
+    fn test_withdraw_batch_call_and_unwrap() {
+        let mut vault = setup();
+        vault.token_decimals.insert(&"token.near".to_string(), &6);
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).build());
+        test_utils::attach_storage_deposit();
+        vault.set_unwrap_near(true);
+        let call = test_utils::withdraw_payload_inst(WITHDRAW_CALL_METADATA, "token.near", "dapp.near", 5, [1; 32], "stake");
+        let unwrap = test_utils::withdraw_inst(wnear().as_str(), accounts(1).as_str(), 5, [2; 32]);
+        let requests = vec![
+            test_utils::shielded_request(&mut vault, &call),
+            test_utils::shielded_request(&mut vault, &unwrap),
+        ];
+        test_utils::attach_storage_deposit();
+        assert!(matches!(vault.withdraw_batch(requests, None), PromiseOrValue::Promise(_)));
+
+        // both chains end in callbacks reporting whether the payout arrived
+        with_promise_result(PromiseResult::Successful(b"\"0\"".to_vec()));
+        let called = vault.on_withdraw_call("dapp.near".parse().unwrap(), "token.near".parse().unwrap(), U128(5));
+        with_promise_result(PromiseResult::Failed);
+        let unwrapped = vault.on_unwrap_near(accounts(1), wnear(), U128(5));
+        assert!(!called && !unwrapped);
+
+        let results = || vec![
+            WithdrawResult { index: 0, tx_id: Some(hex::encode([1; 32])), error: None },
+            WithdrawResult { index: 1, tx_id: Some(hex::encode([2; 32])), error: None },
+        ];
+        testing_env!(
+            VMContextBuilder::new().build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![
+                PromiseResult::Successful(serde_json::to_vec(&called).unwrap()),
+                PromiseResult::Successful(serde_json::to_vec(&unwrapped).unwrap()),
+            ]
+        );
+        let failed = vault.on_withdraw_batch(results());
+        assert!(failed.iter().all(|result| result.error.as_deref() == Some(TRANSFER_FAILED)));
+
+        testing_env!(
+            VMContextBuilder::new().build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(b"true".to_vec()), PromiseResult::Successful(b"true".to_vec())]
+        );
+        assert_eq!(vault.on_withdraw_batch(results()), results());
+    }
+
+    #[test]
+    #[should_panic(expected = "Attached deposit does not cover storage used by this call")]
+    fn test_set_unwrap_near_without_storage_deposit() {
+        let mut vault = setup();
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).build());
+        vault.set_unwrap_near(true);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only admin can call this method")]
+    fn test_set_wrap_near_not_admin() {
+        let mut vault = setup();
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).build());
+        vault.set_wrap_near(None);
+    }
+}
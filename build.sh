@@ -1,5 +1,5 @@
 #!/bin/bash
 set -e
 
-RUSTFLAGS='-C link-arg=-s' cargo build --target wasm32-unknown-unknown --release
-cp target/wasm32-unknown-unknown/release/staking_pool.wasm ./res/
+# only the contracts, the relayer is a native binary
+RUSTFLAGS='-C link-arg=-s' cargo build --target wasm32-unknown-unknown --release -p bridge -p proxy
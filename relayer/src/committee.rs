@@ -0,0 +1,138 @@
+//! signer paths for committees the vault stores as a merkle root, see bridge `Committee::MerkleRoot`
+
+use sha3::{Digest, Keccak256};
+use std::collections::HashMap;
+
+use crate::Error;
+
+fn keccak256(bytes: &[u8]) -> [u8; 32] {
+    Keccak256::digest(bytes).into()
+}
+
+fn hash_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    keccak256(&[&left[..], &right[..]].concat())
+}
+
+/// tree leaves, keccak of each address padded with zero hashes to a power of two
+fn leaves(addresses: &[[u8; 20]]) -> Vec<[u8; 32]> {
+    let mut level: Vec<[u8; 32]> = addresses.iter().map(|address| keccak256(address)).collect();
+    level.resize(addresses.len().max(1).next_power_of_two(), [0; 32]);
+    level
+}
+
+/// merkle root over member addresses, same as the vault builds it
+pub fn committee_root(addresses: &[[u8; 20]]) -> [u8; 32] {
+    let mut level = leaves(addresses);
+    while level.len() > 1 {
+        level = level.chunks(2).map(|pair| hash_pair(&pair[0], &pair[1])).collect();
+    }
+    level[0]
+}
+
+/// sibling hashes from the leaf of member `index` up to the root
+pub fn committee_path(addresses: &[[u8; 20]], index: usize) -> Vec<[u8; 32]> {
+    let mut level = leaves(addresses);
+    let mut index = index;
+    let mut path = vec![];
+    while level.len() > 1 {
+        path.push(level[index ^ 1]);
+        level = level.chunks(2).map(|pair| hash_pair(&pair[0], &pair[1])).collect();
+        index /= 2;
+    }
+    path
+}
+
+/// paths proving each signer of `indexes` belongs to `committee`, as returned by the vault
+/// committee views. empty unless the committee is a single merkle root, whose members
+/// are looked up in `members` by hex root
+pub fn signer_paths(
+    committee: &[String],
+    members: &HashMap<String, Vec<String>>,
+    indexes: &[u8],
+) -> Result<Vec<Vec<[u8; 32]>>, Error> {
+    let root = match committee {
+        [root] if root.len() == 64 => root,
+        _ => return Ok(vec![]),
+    };
+    let addresses = members.get(root)
+        .ok_or_else(|| format!("no members configured for committee root {}", root))?
+        .iter()
+        .map(|address| {
+            let bytes = hex::decode(address)?;
+            <[u8; 20]>::try_from(bytes.as_slice()).map_err(|_| format!("invalid member address {}", address).into())
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    if hex::encode(committee_root(&addresses)) != *root {
+        return Err(format!("configured members do not match committee root {}", root).into());
+    }
+    indexes.iter()
+        .map(|index| match (*index as usize) < addresses.len() {
+            true => Ok(committee_path(&addresses, *index as usize)),
+            false => Err(format!("signer index {} out of committee", index).into()),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addresses() -> Vec<[u8; 20]> {
+        vec![[1; 20], [2; 20], [3; 20]]
+    }
+
+    fn members() -> HashMap<String, Vec<String>> {
+        let root = hex::encode(committee_root(&addresses()));
+        HashMap::from([(root, addresses().iter().map(hex::encode).collect())])
+    }
+
+    // rebuild the root like the vault does when verifying a signer
+    fn member_root(address: &[u8; 20], index: usize, path: &[[u8; 32]]) -> [u8; 32] {
+        let mut node = keccak256(address);
+        for (level, sibling) in path.iter().enumerate() {
+            node = if (index >> level) & 1 == 0 {
+                hash_pair(&node, sibling)
+            } else {
+                hash_pair(sibling, &node)
+            };
+        }
+        node
+    }
+
+    #[test]
+    fn test_committee_root_matches_vault() {
+        // bridge `committee_root` of the same addresses
+        assert_eq!(
+            hex::encode(committee_root(&addresses())),
+            "3b9bfea3b859ff62d774807822bf0b4e4f628c93f8c1a2df4f8175ecdb268c60",
+        );
+    }
+
+    #[test]
+    fn test_signer_paths() {
+        let root = hex::encode(committee_root(&addresses()));
+        let paths = signer_paths(&[root], &members(), &[0, 2]).unwrap();
+        assert_eq!(paths.len(), 2);
+        assert_eq!(paths[0].len(), 2);
+        assert_eq!(member_root(&[1; 20], 0, &paths[0]), committee_root(&addresses()));
+        assert_eq!(member_root(&[3; 20], 2, &paths[1]), committee_root(&addresses()));
+    }
+
+    #[test]
+    fn test_signer_paths_of_key_committee() {
+        let keys = vec!["ab".repeat(64), "cd".repeat(64)];
+        assert!(signer_paths(&keys, &HashMap::new(), &[0, 1]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_signer_paths_errors() {
+        let committee = vec![hex::encode(committee_root(&addresses()))];
+        let root = &committee[0];
+        assert!(signer_paths(&committee, &HashMap::new(), &[0]).is_err());
+        assert!(signer_paths(&committee, &members(), &[3]).is_err());
+
+        let mut members = members();
+        members.get_mut(root).unwrap().pop();
+        assert!(signer_paths(&committee, &members, &[0]).is_err());
+    }
+}
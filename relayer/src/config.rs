@@ -0,0 +1,85 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::Error;
+
+/// env variable holding the signer secret key, never stored in the config file
+pub const SECRET_KEY_ENV: &str = "RELAYER_SECRET_KEY";
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Config {
+    // incognito full node json-rpc endpoint
+    pub incognito_rpc: String,
+    // near json-rpc endpoint
+    pub near_rpc: String,
+    // vault contract account
+    pub vault_id: String,
+    // account paying for withdraw transactions
+    pub signer_id: String,
+    // file keeping relayer progress
+    pub state_path: String,
+    // first beacon height to scan when no state exists
+    #[serde(default)]
+    pub start_height: u64,
+    #[serde(default = "default_poll_interval_secs")]
+    pub poll_interval_secs: u64,
+    #[serde(default = "default_max_blocks_per_poll")]
+    pub max_blocks_per_poll: u64,
+    // withdraw submissions per burn within one poll
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    // base delay between submissions, doubled after each failure
+    #[serde(default = "default_retry_delay_ms")]
+    pub retry_delay_ms: u64,
+    // member addresses of committees the vault stores as a merkle root, keyed by hex
+    // root, as logged by the vault `compact_committee`
+    #[serde(default)]
+    pub committee_members: HashMap<String, Vec<String>>,
+}
+
+fn default_poll_interval_secs() -> u64 {
+    30
+}
+
+fn default_max_blocks_per_poll() -> u64 {
+    100
+}
+
+fn default_max_attempts() -> u32 {
+    3
+}
+
+fn default_retry_delay_ms() -> u64 {
+    2_000
+}
+
+impl Config {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let raw = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&raw)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults() {
+        let config: Config = serde_json::from_str(r#"{
+            "incognito_rpc": "http://127.0.0.1:9334",
+            "near_rpc": "https://rpc.testnet.near.org",
+            "vault_id": "vault.testnet",
+            "signer_id": "relayer.testnet",
+            "state_path": "state.json"
+        }"#).unwrap();
+        assert_eq!(config.start_height, 0);
+        assert_eq!(config.poll_interval_secs, 30);
+        assert_eq!(config.max_blocks_per_poll, 100);
+        assert_eq!(config.max_attempts, 3);
+        assert_eq!(config.retry_delay_ms, 2_000);
+        assert!(config.committee_members.is_empty());
+    }
+}
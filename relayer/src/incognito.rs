@@ -0,0 +1,280 @@
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::vault::InteractRequest;
+use crate::Error;
+
+/// metadata type of burn confirm instructions unshielding to Near
+pub const WITHDRAW_METADATA: &str = "157";
+/// metadata type of burn confirm instructions unshielding to a Near contract with a message
+pub const WITHDRAW_CALL_METADATA: &str = "162";
+/// metadata type of burn confirm instructions unshielding to Near with a transfer memo
+pub const WITHDRAW_MEMO_METADATA: &str = "163";
+/// metadata type of burn confirm instructions unshielding to Near with a relayer fee
+pub const WITHDRAW_FEE_METADATA: &str = "164";
+/// metadata type of burn confirm instructions releasing an NFT locked on Near
+pub const NFT_WITHDRAW_METADATA: &str = "165";
+/// metadata type of burn confirm instructions unshielding a NEP-245 multi token id
+pub const MT_WITHDRAW_METADATA: &str = "166";
+/// metadata type of burn confirm instructions unshielding to Near an amount wider than 64 bits
+pub const WITHDRAW_WIDE_METADATA: &str = "167";
+/// position of the burn tx id in a beacon burn confirm instruction
+pub const BURN_TX_ID_INDEX: usize = 5;
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct RpcResponse<T> {
+    result: Option<T>,
+    error: Option<RpcError>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct BeaconBestState {
+    beacon_height: u64,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct BeaconBlock {
+    #[serde(default)]
+    instructions: Vec<Vec<String>>,
+}
+
+/// result of `getnearburnproof`
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct BurnProof {
+    pub instruction: String,
+    // hex encoded beacon height
+    pub beacon_height: String,
+    pub beacon_inst_path: Vec<String>,
+    pub beacon_inst_path_is_left: Vec<bool>,
+    pub beacon_inst_root: String,
+    pub beacon_blk_data: String,
+    pub beacon_sig_idxs: Vec<u8>,
+    // 64 bytes signature followed by recovery id, hex encoded
+    pub beacon_sigs: Vec<String>,
+    // same proof signed by the bridge committee, empty when not requested
+    #[serde(default)]
+    pub bridge_height: String,
+    #[serde(default)]
+    pub bridge_inst_path: Vec<String>,
+    #[serde(default)]
+    pub bridge_inst_path_is_left: Vec<bool>,
+    #[serde(default)]
+    pub bridge_inst_root: String,
+    #[serde(default)]
+    pub bridge_blk_data: String,
+    #[serde(default)]
+    pub bridge_sig_idxs: Vec<u8>,
+    #[serde(default)]
+    pub bridge_sigs: Vec<String>,
+}
+
+impl BurnProof {
+    /// build the `withdraw` argument the same way `test/withdrawtests.js` does
+    pub fn to_request(&self) -> Result<InteractRequest, Error> {
+        self.request(
+            &self.beacon_height,
+            &self.beacon_inst_path,
+            &self.beacon_inst_path_is_left,
+            &self.beacon_inst_root,
+            &self.beacon_blk_data,
+            &self.beacon_sig_idxs,
+            &self.beacon_sigs,
+        )
+    }
+
+    /// build the `bridge_info` argument vaults verifying bridge committees require
+    pub fn to_bridge_request(&self) -> Result<InteractRequest, Error> {
+        if self.bridge_sigs.is_empty() {
+            return Err("burn proof has no bridge committee signatures".into());
+        }
+        self.request(
+            &self.bridge_height,
+            &self.bridge_inst_path,
+            &self.bridge_inst_path_is_left,
+            &self.bridge_inst_root,
+            &self.bridge_blk_data,
+            &self.bridge_sig_idxs,
+            &self.bridge_sigs,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn request(
+        &self,
+        height: &str,
+        inst_path: &[String],
+        inst_path_is_left: &[bool],
+        inst_root: &str,
+        blk_data: &str,
+        sig_idxs: &[u8],
+        sigs: &[String],
+    ) -> Result<InteractRequest, Error> {
+        let height = u128::from_str_radix(height.trim_start_matches("0x"), 16)?;
+        let inst_paths = inst_path.iter()
+            .map(|path| to_32_bytes(path))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut signatures = vec![];
+        let mut vs = vec![];
+        for sig in sigs.iter() {
+            if sig.len() <= 128 {
+                return Err(format!("invalid signature {}", sig).into());
+            }
+            let (s_r, v) = sig.split_at(128);
+            signatures.push(s_r.to_string());
+            vs.push(v.parse::<u8>()?);
+        }
+
+        Ok(InteractRequest {
+            inst: self.instruction.clone(),
+            height,
+            inst_paths,
+            inst_path_is_lefts: inst_path_is_left.to_vec(),
+            inst_root: to_32_bytes(inst_root)?,
+            blk_data: to_32_bytes(blk_data)?,
+            indexes: sig_idxs.to_vec(),
+            signatures,
+            vs,
+            signer_paths: vec![],
+        })
+    }
+}
+
+/// decode hex string into left aligned 32 bytes
+fn to_32_bytes(hex_str: &str) -> Result<[u8; 32], Error> {
+    let bytes = hex::decode(hex_str)?;
+    if bytes.len() > 32 {
+        return Err(format!("{} longer than 32 bytes", hex_str).into());
+    }
+    let mut bytes_ = [0u8; 32];
+    bytes_[..bytes.len()].copy_from_slice(&bytes);
+    Ok(bytes_)
+}
+
+pub struct IncognitoClient {
+    url: String,
+    http: reqwest::Client,
+}
+
+impl IncognitoClient {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            http: reqwest::Client::new(),
+        }
+    }
+
+    async fn call<T: DeserializeOwned>(&self, method: &str, params: Value) -> Result<T, Error> {
+        let response: RpcResponse<T> = self.http
+            .post(&self.url)
+            .json(&json!({
+                "jsonrpc": "1.0",
+                "method": method,
+                "params": params,
+                "id": 1,
+            }))
+            .send()
+            .await?
+            .json()
+            .await?;
+        if let Some(err) = response.error {
+            return Err(format!("{} failed ({}): {}", method, err.code, err.message).into());
+        }
+        response.result.ok_or_else(|| format!("{} returned no result", method).into())
+    }
+
+    /// latest beacon height
+    pub async fn beacon_height(&self) -> Result<u64, Error> {
+        let state: BeaconBestState = self.call("getbeaconbeststate", json!([])).await?;
+        Ok(state.beacon_height)
+    }
+
+    /// burn tx ids unshielding to Near confirmed at beacon height
+    pub async fn burn_txs_at(&self, height: u64) -> Result<Vec<String>, Error> {
+        let blocks: Vec<BeaconBlock> = self.call("retrievebeaconblockbyheight", json!([height, "2"])).await?;
+        let tx_ids = blocks.iter()
+            .flat_map(|block| block.instructions.iter())
+            .filter(|inst| matches!(inst.first().map(String::as_str), Some(WITHDRAW_METADATA | WITHDRAW_CALL_METADATA | WITHDRAW_MEMO_METADATA | WITHDRAW_FEE_METADATA | NFT_WITHDRAW_METADATA | MT_WITHDRAW_METADATA | WITHDRAW_WIDE_METADATA)))
+            .filter_map(|inst| inst.get(BURN_TX_ID_INDEX).cloned())
+            .collect();
+        Ok(tx_ids)
+    }
+
+    /// proof of burn tx for vault withdraw
+    pub async fn burn_proof(&self, tx_id: &str) -> Result<BurnProof, Error> {
+        self.call("getnearburnproof", json!([tx_id])).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock;
+
+    #[test]
+    fn test_to_request() {
+        let proof: BurnProof = serde_json::from_value(mock::burn_proof("9d01")).unwrap();
+        let request = proof.to_request().unwrap();
+        assert_eq!(request.height, 16);
+        assert_eq!(request.inst_paths.len(), 1);
+        assert_eq!(request.signatures[0].len(), 128);
+        assert_eq!(request.vs, vec![0, 1]);
+        assert_eq!(request.inst_root[0], 0x45);
+    }
+
+    #[test]
+    fn test_to_bridge_request() {
+        let proof: BurnProof = serde_json::from_value(mock::burn_proof("9d01")).unwrap();
+        let request = proof.to_bridge_request().unwrap();
+        assert_eq!(request.height, 16);
+        assert_eq!(request.indexes, vec![1]);
+        assert_eq!(request.vs, vec![1]);
+        assert_eq!(request.inst, "9d01");
+
+        let mut value = mock::burn_proof("9d01");
+        value["BridgeSigs"] = json!([]);
+        let proof: BurnProof = serde_json::from_value(value).unwrap();
+        assert!(proof.to_bridge_request().is_err());
+    }
+
+    #[test]
+    fn test_to_32_bytes_pads_right() {
+        let bytes = to_32_bytes("0102").unwrap();
+        assert_eq!(bytes[..2], [1, 2]);
+        assert_eq!(bytes[2..], [0u8; 30]);
+        assert!(to_32_bytes(&"00".repeat(33)).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rpc_calls() {
+        let url = mock::serve(|method, params| match method {
+            "getbeaconbeststate" => json!({ "Result": { "BeaconHeight": 12 }, "Error": null }),
+            "retrievebeaconblockbyheight" => {
+                assert_eq!(params[0], 11);
+                json!({ "Result": [{ "Instructions": [
+                    ["157", "1", "token", "receiver", "amount", "burn-tx"],
+                    ["158", "1", "committee"],
+                    ["162", "1", "token", "dapp", "amount", "call-tx", "msg"],
+                ] }], "Error": null })
+            }
+            "getnearburnproof" => json!({ "Result": null, "Error": { "Code": -1, "Message": "not found" } }),
+            _ => unreachable!(),
+        });
+        let client = IncognitoClient::new(&url);
+        assert_eq!(client.beacon_height().await.unwrap(), 12);
+        assert_eq!(client.burn_txs_at(11).await.unwrap(), vec!["burn-tx".to_string(), "call-tx".to_string()]);
+        let err = client.burn_proof("burn-tx").await.unwrap_err();
+        assert!(err.to_string().contains("not found"));
+    }
+}
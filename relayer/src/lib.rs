@@ -0,0 +1,19 @@
+/*!
+Incognito -> Near relayer.
+NOTES:
+  - Watch Incognito beacon blocks for burn instructions targeting Near
+  - Fetch burn proofs and submit them to the vault `withdraw` method
+  - Persist scanned height and pending burns locally so restarts resume
+*/
+
+pub mod committee;
+pub mod config;
+pub mod incognito;
+pub mod relayer;
+pub mod store;
+pub mod vault;
+
+#[cfg(test)]
+mod mock;
+
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
@@ -0,0 +1,24 @@
+use std::env;
+use std::time::Duration;
+
+use relayer::config::{Config, SECRET_KEY_ENV};
+use relayer::relayer::Relayer;
+use relayer::vault::NearVault;
+use relayer::Error;
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let config_path = env::args().nth(1).unwrap_or_else(|| "relayer.json".to_string());
+    let config = Config::load(&config_path)?;
+    let secret_key = env::var(SECRET_KEY_ENV)
+        .map_err(|_| format!("{} must hold the signer secret key", SECRET_KEY_ENV))?;
+
+    let vault = NearVault::new(&config.near_rpc, &config.vault_id, &config.signer_id, &secret_key)?;
+    let mut relayer = Relayer::new(&config, vault)?;
+    loop {
+        if let Err(err) = relayer.poll().await {
+            eprintln!("poll failed: {}", err);
+        }
+        tokio::time::sleep(Duration::from_secs(config.poll_interval_secs)).await;
+    }
+}
@@ -0,0 +1,75 @@
+//! minimal json-rpc server answering incognito calls in tests
+
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::thread;
+
+/// serve `handler(method, params)` responses on a local port and return its url
+pub fn serve<F>(handler: F) -> String
+where
+    F: Fn(&str, &Value) -> Value + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => continue,
+            };
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap_or(0) == 0 || line == "\r\n" {
+                    break;
+                }
+                let lower = line.to_ascii_lowercase();
+                if let Some(value) = lower.strip_prefix("content-length:") {
+                    content_length = value.trim().parse().unwrap_or(0);
+                }
+            }
+            let mut body = vec![0u8; content_length];
+            if reader.read_exact(&mut body).is_err() {
+                continue;
+            }
+            let request: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+            let method = request["method"].as_str().unwrap_or_default();
+            let response = handler(method, &request["params"]).to_string();
+            let _ = write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                response.len(),
+                response
+            );
+        }
+    });
+    url
+}
+
+/// `getnearburnproof` result carrying instruction `inst`, signed by beacon and bridge committees
+pub fn burn_proof(inst: &str) -> Value {
+    json!({
+        "Instruction": inst,
+        "BeaconHeight": "10",
+        "BeaconInstPath": ["23abf9d3acf3fde6246cce9e392c2154ab8423d8d2e01053e74db7f6d17aea4f"],
+        "BeaconInstPathIsLeft": [false],
+        "BeaconInstRoot": "45e6d8d759bc5993097236e5f2d17053969f0b769bb1d0f8e222b6c40a0f6af3",
+        "BeaconBlkData": "eff9f595401e37992a3a1fb0c1908e0d4bb2105eae42c0ef6499483b991f2c91",
+        "BeaconSigIdxs": [0, 1],
+        "BeaconSigs": [
+            "3ba689cfbcbfe81d10f47c0becd911ece7fd1c99ce3bf84c61cf20f3bfc2979438251b39a913e934bd6b61def19fac8da98808cce9b8f428809885364a49d81c0",
+            "fbb1705370519af0e89fa86ced533123a8a33db842a3d90a7c8c69ee82ce20c44ecf63c0f1646d7f2d173b7d4dae99c16e29af1bedcc5ee1a88e15c132f271361",
+        ],
+        "BridgeHeight": "10",
+        "BridgeInstPath": ["23abf9d3acf3fde6246cce9e392c2154ab8423d8d2e01053e74db7f6d17aea4f"],
+        "BridgeInstPathIsLeft": [false],
+        "BridgeInstRoot": "45e6d8d759bc5993097236e5f2d17053969f0b769bb1d0f8e222b6c40a0f6af3",
+        "BridgeBlkData": "eff9f595401e37992a3a1fb0c1908e0d4bb2105eae42c0ef6499483b991f2c91",
+        "BridgeSigIdxs": [1],
+        "BridgeSigs": [
+            "fbb1705370519af0e89fa86ced533123a8a33db842a3d90a7c8c69ee82ce20c44ecf63c0f1646d7f2d173b7d4dae99c16e29af1bedcc5ee1a88e15c132f271361",
+        ],
+    })
+}
@@ -0,0 +1,303 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::time::sleep;
+
+use crate::committee::signer_paths;
+use crate::config::Config;
+use crate::incognito::IncognitoClient;
+use crate::store::Store;
+use crate::vault::{bump_gas, estimate_gas, InteractRequest, Vault};
+use crate::Error;
+
+pub struct Relayer<V: Vault> {
+    incognito: IncognitoClient,
+    vault: V,
+    store: Store,
+    max_blocks_per_poll: u64,
+    max_attempts: u32,
+    retry_delay: Duration,
+    committee_members: HashMap<String, Vec<String>>,
+}
+
+impl<V: Vault> Relayer<V> {
+    pub fn new(config: &Config, vault: V) -> Result<Self, Error> {
+        Ok(Self {
+            incognito: IncognitoClient::new(&config.incognito_rpc),
+            vault,
+            store: Store::open(&config.state_path, config.start_height)?,
+            max_blocks_per_poll: config.max_blocks_per_poll,
+            max_attempts: config.max_attempts.max(1),
+            retry_delay: Duration::from_millis(config.retry_delay_ms),
+            committee_members: config.committee_members.clone(),
+        })
+    }
+
+    pub fn store(&self) -> &Store {
+        &self.store
+    }
+
+    /// scan new beacon blocks then try to withdraw every pending burn
+    pub async fn poll(&mut self) -> Result<(), Error> {
+        self.scan().await?;
+        let pending: Vec<String> = self.store.state.pending.iter().cloned().collect();
+        for tx_id in pending {
+            match self.relay(&tx_id).await {
+                Ok(()) => {
+                    self.store.state.pending.remove(&tx_id);
+                    self.store.save()?;
+                }
+                Err(err) => eprintln!("burn {} not relayed yet: {}", tx_id, err),
+            }
+        }
+        Ok(())
+    }
+
+    /// collect burn tx ids from beacon blocks, saving progress after each block
+    async fn scan(&mut self) -> Result<(), Error> {
+        let latest = self.incognito.beacon_height().await?;
+        let end = latest.min(self.store.state.next_height.saturating_add(self.max_blocks_per_poll.max(1) - 1));
+        while self.store.state.next_height <= end {
+            let height = self.store.state.next_height;
+            for tx_id in self.incognito.burn_txs_at(height).await? {
+                self.store.state.pending.insert(tx_id);
+            }
+            self.store.state.next_height = height + 1;
+            self.store.save()?;
+        }
+        Ok(())
+    }
+
+    /// submit withdraw for a burn unless the vault already processed it
+    async fn relay(&self, tx_id: &str) -> Result<(), Error> {
+        let proof = self.incognito.burn_proof(tx_id).await?;
+        let mut request = proof.to_request()?;
+        self.add_signer_paths(&mut request, false).await?;
+        let bridge = match self.vault.requires_bridge_proof().await? {
+            true => {
+                let mut bridge = proof.to_bridge_request()?;
+                self.add_signer_paths(&mut bridge, true).await?;
+                Some(bridge)
+            }
+            false => None,
+        };
+        let burn_id = request.tx_id()?;
+        let mut gas = estimate_gas(&request, bridge.as_ref());
+        let mut delay = self.retry_delay;
+        let mut last_err = None;
+        for attempt in 0..self.max_attempts {
+            // another relayer may have landed it meanwhile
+            if self.vault.is_burn_used(&burn_id).await? {
+                return Ok(());
+            }
+            if attempt > 0 {
+                sleep(delay).await;
+                delay *= 2;
+                gas = bump_gas(gas);
+            }
+            match self.vault.withdraw(&request, bridge.as_ref(), gas).await {
+                Ok(hash) => {
+                    println!("burn {} withdrawn in {}", tx_id, hash);
+                    return Ok(());
+                }
+                Err(err) => last_err = Some(err),
+            }
+        }
+        Err(last_err.unwrap_or_else(|| "no withdraw attempt".into()))
+    }
+
+    /// prove signers are members when the vault stores their committee as a merkle root
+    async fn add_signer_paths(&self, request: &mut InteractRequest, bridge: bool) -> Result<(), Error> {
+        let committee = self.vault.committee(request.height, bridge).await?;
+        request.signer_paths = signer_paths(&committee, &self.committee_members, &request.indexes)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::committee::committee_root;
+    use crate::mock;
+    use serde_json::json;
+    use std::cell::RefCell;
+
+    #[derive(Default)]
+    struct MockVault {
+        used: RefCell<bool>,
+        failures: RefCell<u32>,
+        withdrawals: RefCell<Vec<u64>>,
+        bridge_policy: bool,
+        // committee views, public keys when empty
+        committee: Vec<String>,
+        requests: RefCell<Vec<(InteractRequest, Option<InteractRequest>)>>,
+    }
+
+    impl Vault for &MockVault {
+        async fn is_burn_used(&self, _tx_id: &[u8; 32]) -> Result<bool, Error> {
+            Ok(*self.used.borrow())
+        }
+
+        async fn requires_bridge_proof(&self) -> Result<bool, Error> {
+            Ok(self.bridge_policy)
+        }
+
+        async fn committee(&self, _height: u128, _bridge: bool) -> Result<Vec<String>, Error> {
+            match self.committee.is_empty() {
+                true => Ok(vec!["ab".repeat(64), "cd".repeat(64)]),
+                false => Ok(self.committee.clone()),
+            }
+        }
+
+        async fn withdraw(&self, request: &InteractRequest, bridge: Option<&InteractRequest>, gas: u64) -> Result<String, Error> {
+            self.withdrawals.borrow_mut().push(gas);
+            self.requests.borrow_mut().push((request.clone(), bridge.cloned()));
+            if *self.failures.borrow() > 0 {
+                *self.failures.borrow_mut() -= 1;
+                return Err("Exceeded the prepaid gas".into());
+            }
+            *self.used.borrow_mut() = true;
+            Ok("near-tx-hash".to_string())
+        }
+    }
+
+    fn withdraw_inst() -> String {
+        let mut inst = vec![157u8, 1];
+        inst.resize(164, 0);
+        inst.extend_from_slice(&[9u8; 32]);
+        hex::encode(inst)
+    }
+
+    fn config(name: &str, url: &str) -> Config {
+        let path = std::env::temp_dir().join(format!("relayer-{}-{}.json", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        Config {
+            incognito_rpc: url.to_string(),
+            near_rpc: String::new(),
+            vault_id: "vault.testnet".to_string(),
+            signer_id: "relayer.testnet".to_string(),
+            state_path: path.to_string_lossy().to_string(),
+            start_height: 1,
+            poll_interval_secs: 0,
+            max_blocks_per_poll: 10,
+            max_attempts: 3,
+            retry_delay_ms: 1,
+            committee_members: HashMap::new(),
+        }
+    }
+
+    fn incognito() -> String {
+        mock::serve(|method, params| match method {
+            "getbeaconbeststate" => json!({ "Result": { "BeaconHeight": 3 }, "Error": null }),
+            "retrievebeaconblockbyheight" if params[0] == 2 => json!({ "Result": [{ "Instructions": [
+                ["157", "1", "token", "receiver", "amount", "burn-tx"],
+            ] }], "Error": null }),
+            "retrievebeaconblockbyheight" => json!({ "Result": [{ "Instructions": [] }], "Error": null }),
+            "getnearburnproof" => json!({ "Result": mock::burn_proof(&withdraw_inst()), "Error": null }),
+            _ => unreachable!(),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_poll_withdraws_and_persists() {
+        let config = config("withdraw", &incognito());
+        let vault = MockVault::default();
+        let mut relayer = Relayer::new(&config, &vault).unwrap();
+        relayer.poll().await.unwrap();
+
+        assert_eq!(vault.withdrawals.borrow().len(), 1);
+        assert_eq!(relayer.store().state.next_height, 4);
+        assert!(relayer.store().state.pending.is_empty());
+
+        // restart resumes from saved height without resubmitting
+        let mut relayer = Relayer::new(&config, &vault).unwrap();
+        assert_eq!(relayer.store().state.next_height, 4);
+        relayer.poll().await.unwrap();
+        assert_eq!(vault.withdrawals.borrow().len(), 1);
+        std::fs::remove_file(&config.state_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_skip_used_burn() {
+        let config = config("used", &incognito());
+        let vault = MockVault::default();
+        *vault.used.borrow_mut() = true;
+        let mut relayer = Relayer::new(&config, &vault).unwrap();
+        relayer.poll().await.unwrap();
+
+        assert!(vault.withdrawals.borrow().is_empty());
+        assert!(relayer.store().state.pending.is_empty());
+        std::fs::remove_file(&config.state_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_retry_bumps_gas() {
+        let config = config("retry", &incognito());
+        let vault = MockVault::default();
+        *vault.failures.borrow_mut() = 2;
+        let mut relayer = Relayer::new(&config, &vault).unwrap();
+        relayer.poll().await.unwrap();
+
+        let withdrawals = vault.withdrawals.borrow();
+        assert_eq!(withdrawals.len(), 3);
+        assert!(withdrawals[0] < withdrawals[1] && withdrawals[1] < withdrawals[2]);
+        assert!(relayer.store().state.pending.is_empty());
+        std::fs::remove_file(&config.state_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_keep_pending_after_max_attempts() {
+        let config = config("pending", &incognito());
+        let vault = MockVault::default();
+        *vault.failures.borrow_mut() = 5;
+        let mut relayer = Relayer::new(&config, &vault).unwrap();
+        relayer.poll().await.unwrap();
+
+        assert_eq!(vault.withdrawals.borrow().len(), 3);
+        assert!(relayer.store().state.pending.contains("burn-tx"));
+        std::fs::remove_file(&config.state_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_withdraw_with_bridge_proof() {
+        let config = config("bridge", &incognito());
+        let vault = MockVault { bridge_policy: true, ..Default::default() };
+        let mut relayer = Relayer::new(&config, &vault).unwrap();
+        relayer.poll().await.unwrap();
+
+        let requests = vault.requests.borrow();
+        let (request, bridge) = &requests[0];
+        assert_eq!(request.indexes, vec![0, 1]);
+        assert_eq!(bridge.as_ref().unwrap().indexes, vec![1]);
+        assert!(request.signer_paths.is_empty());
+        std::fs::remove_file(&config.state_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_withdraw_with_signer_paths() {
+        let mut config = config("paths", &incognito());
+        let members = vec![[1u8; 20], [2; 20], [3; 20]];
+        let root = hex::encode(committee_root(&members));
+        config.committee_members.insert(root.clone(), members.iter().map(hex::encode).collect());
+        let vault = MockVault { committee: vec![root], ..Default::default() };
+        let mut relayer = Relayer::new(&config, &vault).unwrap();
+        relayer.poll().await.unwrap();
+
+        let requests = vault.requests.borrow();
+        let (request, bridge) = &requests[0];
+        assert_eq!(request.signer_paths.len(), 2);
+        assert!(bridge.is_none());
+        std::fs::remove_file(&config.state_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_keep_pending_without_committee_members() {
+        let config = config("members", &incognito());
+        let vault = MockVault { committee: vec!["11".repeat(32)], ..Default::default() };
+        let mut relayer = Relayer::new(&config, &vault).unwrap();
+        relayer.poll().await.unwrap();
+
+        assert!(vault.withdrawals.borrow().is_empty());
+        assert!(relayer.store().state.pending.contains("burn-tx"));
+        std::fs::remove_file(&config.state_path).unwrap();
+    }
+}
@@ -0,0 +1,62 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::Error;
+
+/// relayer progress persisted between runs
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct State {
+    // next beacon height to scan
+    pub next_height: u64,
+    // burn tx ids found but not yet withdrawn
+    pub pending: BTreeSet<String>,
+}
+
+pub struct Store {
+    path: PathBuf,
+    pub state: State,
+}
+
+impl Store {
+    /// load state from `path`, starting at `start_height` when no state exists yet
+    pub fn open(path: impl Into<PathBuf>, start_height: u64) -> Result<Self, Error> {
+        let path = path.into();
+        let state = if path.exists() {
+            serde_json::from_str(&fs::read_to_string(&path)?)?
+        } else {
+            State { next_height: start_height, ..State::default() }
+        };
+        Ok(Self { path, state })
+    }
+
+    /// write state atomically so a crash never leaves a truncated file
+    pub fn save(&self) -> Result<(), Error> {
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(&self.state)?)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open_and_save() {
+        let path = std::env::temp_dir().join(format!("relayer-store-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut store = Store::open(&path, 42).unwrap();
+        assert_eq!(store.state.next_height, 42);
+        store.state.next_height = 50;
+        store.state.pending.insert("burn-tx".to_string());
+        store.save().unwrap();
+
+        let reopened = Store::open(&path, 0).unwrap();
+        assert_eq!(reopened.state, store.state);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,255 @@
+use near_crypto::{InMemorySigner, SecretKey};
+use near_jsonrpc_client::{methods, JsonRpcClient};
+use near_jsonrpc_primitives::types::query::QueryResponseKind;
+use near_primitives::transaction::{Action, FunctionCallAction, Transaction};
+use near_primitives::types::{AccountId, BlockReference, Finality, FunctionArgs};
+use near_primitives::views::{FinalExecutionStatus, QueryRequest};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::Error;
+
+pub const TGAS: u64 = 1_000_000_000_000;
+/// gas limit of a single function call
+pub const MAX_GAS: u64 = 300 * TGAS;
+// parsing, storage and the ft_transfer / native transfer promise
+const BASE_GAS: u64 = 30 * TGAS;
+// ecrecover and hex decoding of one beacon signature
+const GAS_PER_SIGNATURE: u64 = TGAS;
+// one keccak round while rebuilding the instruction root
+const GAS_PER_PATH: u64 = TGAS / 2;
+// pays the storage staking of a withdraw, the vault refunds what is not used
+const STORAGE_DEPOSIT: u128 = 100_000_000_000_000_000_000_000;
+
+// NFT unshields go to `withdraw_nft`, every other burn to `withdraw`
+const NFT_WITHDRAW_METADATA: u8 = 165;
+// tx id is the last 32 bytes of the withdraw instruction, see bridge `LEGACY_WITHDRAW_LAYOUT`
+const INST_TX_ID_OFFSET: usize = 1 + 1 + 1 + 64 + 1 + 64 + 24 + 8;
+// versioned instructions are prefixed with this byte and the 32 bytes vault domain
+const DOMAIN_INST_VERSION: u8 = 2;
+const DOMAIN_HEADER_LEN: usize = 1 + 32;
+// vault `VerificationPolicy` requiring a bridge committee proof
+const BEACON_AND_BRIDGE_POLICY: &str = "BeaconAndBridge";
+
+/// `withdraw` argument, mirror of the bridge `InteractRequest`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InteractRequest {
+    // instruction in bytes
+    pub inst: String,
+    // beacon height
+    pub height: u128,
+    // inst paths to build merkle tree
+    pub inst_paths: Vec<[u8; 32]>,
+    // inst path indicator
+    pub inst_path_is_lefts: Vec<bool>,
+    // instruction root
+    pub inst_root: [u8; 32],
+    // blkData
+    pub blk_data: [u8; 32],
+    // signature index
+    pub indexes: Vec<u8>,
+    // signatures
+    pub signatures: Vec<String>,
+    // v value
+    pub vs: Vec<u8>,
+    // merkle paths of signers when the committee is stored as a root
+    #[serde(default)]
+    pub signer_paths: Vec<Vec<[u8; 32]>>,
+}
+
+impl InteractRequest {
+    /// instruction without its domain header
+    fn body(&self) -> Result<Vec<u8>, Error> {
+        let inst = hex::decode(&self.inst)?;
+        if inst.first() == Some(&DOMAIN_INST_VERSION) {
+            return Ok(inst.get(DOMAIN_HEADER_LEN..).ok_or("instruction too short")?.to_vec());
+        }
+        Ok(inst)
+    }
+
+    /// burn tx id the vault marks as used
+    pub fn tx_id(&self) -> Result<[u8; 32], Error> {
+        let inst = self.body()?;
+        let tx_id = inst.get(INST_TX_ID_OFFSET..INST_TX_ID_OFFSET + 32)
+            .ok_or("instruction too short")?;
+        let mut bytes = [0u8; 32];
+        bytes.copy_from_slice(tx_id);
+        Ok(bytes)
+    }
+
+    /// vault method consuming the instruction
+    pub fn method(&self) -> &'static str {
+        match self.body().ok().and_then(|inst| inst.first().copied()) {
+            Some(NFT_WITHDRAW_METADATA) => "withdraw_nft",
+            _ => "withdraw",
+        }
+    }
+}
+
+/// gas to attach to `withdraw`, scaled by verification work in the proofs
+pub fn estimate_gas(request: &InteractRequest, bridge: Option<&InteractRequest>) -> u64 {
+    let gas = BASE_GAS
+        + std::iter::once(request).chain(bridge).map(|request| {
+            let paths = request.inst_paths.len() + request.signer_paths.iter().map(Vec::len).sum::<usize>();
+            GAS_PER_SIGNATURE * request.signatures.len() as u64 + GAS_PER_PATH * paths as u64
+        }).sum::<u64>();
+    gas.min(MAX_GAS)
+}
+
+/// gas for the next submission after a failed one
+pub fn bump_gas(gas: u64) -> u64 {
+    (gas + gas / 2).min(MAX_GAS)
+}
+
+/// vault methods the relayer depends on
+#[allow(async_fn_in_trait)]
+pub trait Vault {
+    async fn is_burn_used(&self, tx_id: &[u8; 32]) -> Result<bool, Error>;
+
+    /// whether withdraws must also carry a bridge committee proof
+    async fn requires_bridge_proof(&self) -> Result<bool, Error>;
+
+    /// hex encoded beacon or bridge committee at `height`
+    async fn committee(&self, height: u128, bridge: bool) -> Result<Vec<String>, Error>;
+
+    /// submit withdraw and return the near transaction hash
+    async fn withdraw(&self, request: &InteractRequest, bridge: Option<&InteractRequest>, gas: u64) -> Result<String, Error>;
+}
+
+pub struct NearVault {
+    client: JsonRpcClient,
+    vault_id: AccountId,
+    signer: InMemorySigner,
+}
+
+impl NearVault {
+    pub fn new(url: &str, vault_id: &str, signer_id: &str, secret_key: &str) -> Result<Self, Error> {
+        let secret_key: SecretKey = secret_key.parse()?;
+        Ok(Self {
+            client: JsonRpcClient::connect(url),
+            vault_id: vault_id.parse()?,
+            signer: InMemorySigner::from_secret_key(signer_id.parse()?, secret_key),
+        })
+    }
+
+    async fn view<T: DeserializeOwned>(&self, method_name: &str, args: serde_json::Value) -> Result<T, Error> {
+        let response = self.client.call(methods::query::RpcQueryRequest {
+            block_reference: BlockReference::Finality(Finality::Final),
+            request: QueryRequest::CallFunction {
+                account_id: self.vault_id.clone(),
+                method_name: method_name.to_string(),
+                args: FunctionArgs::from(args.to_string().into_bytes()),
+            },
+        }).await?;
+        match response.kind {
+            QueryResponseKind::CallResult(result) => Ok(serde_json::from_slice(&result.result)?),
+            _ => Err(format!("unexpected {} response", method_name).into()),
+        }
+    }
+}
+
+impl Vault for NearVault {
+    async fn is_burn_used(&self, tx_id: &[u8; 32]) -> Result<bool, Error> {
+        self.view("is_burn_processed", json!({ "tx_id": hex::encode(tx_id) })).await
+    }
+
+    async fn requires_bridge_proof(&self) -> Result<bool, Error> {
+        let policy: String = self.view("get_verification_policy", json!({})).await?;
+        Ok(policy == BEACON_AND_BRIDGE_POLICY)
+    }
+
+    async fn committee(&self, height: u128, bridge: bool) -> Result<Vec<String>, Error> {
+        let method_name = if bridge { "get_bridge_committee" } else { "get_beacons" };
+        self.view(method_name, json!({ "height": height })).await
+    }
+
+    async fn withdraw(&self, request: &InteractRequest, bridge: Option<&InteractRequest>, gas: u64) -> Result<String, Error> {
+        let access_key = self.client.call(methods::query::RpcQueryRequest {
+            block_reference: BlockReference::latest(),
+            request: QueryRequest::ViewAccessKey {
+                account_id: self.signer.account_id.clone(),
+                public_key: self.signer.public_key.clone(),
+            },
+        }).await?;
+        let nonce = match access_key.kind {
+            QueryResponseKind::AccessKey(access_key) => access_key.nonce,
+            _ => return Err("failed to extract current nonce".into()),
+        };
+
+        let transaction = Transaction {
+            signer_id: self.signer.account_id.clone(),
+            public_key: self.signer.public_key.clone(),
+            nonce: nonce + 1,
+            receiver_id: self.vault_id.clone(),
+            block_hash: access_key.block_hash,
+            actions: vec![Action::FunctionCall(FunctionCallAction {
+                method_name: request.method().to_string(),
+                args: json!({ "unshield_info": request, "bridge_info": bridge }).to_string().into_bytes(),
+                gas,
+                deposit: STORAGE_DEPOSIT,
+            })],
+        };
+        let outcome = self.client.call(methods::broadcast_tx_commit::RpcBroadcastTxCommitRequest {
+            signed_transaction: transaction.sign(&self.signer),
+        }).await?;
+        match outcome.status {
+            FinalExecutionStatus::SuccessValue(_) => Ok(outcome.transaction.hash.to_string()),
+            status => Err(format!("withdraw {} failed: {:?}", outcome.transaction.hash, status).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(signatures: usize, paths: usize) -> InteractRequest {
+        InteractRequest {
+            inst: String::new(),
+            height: 0,
+            inst_paths: vec![[0; 32]; paths],
+            inst_path_is_lefts: vec![false; paths],
+            inst_root: [0; 32],
+            blk_data: [0; 32],
+            indexes: vec![0; signatures],
+            signatures: vec![String::new(); signatures],
+            vs: vec![0; signatures],
+            signer_paths: vec![],
+        }
+    }
+
+    #[test]
+    fn test_estimate_gas() {
+        assert_eq!(estimate_gas(&request(4, 2), None), 35 * TGAS);
+        assert_eq!(estimate_gas(&request(4, 2), Some(&request(4, 2))), 40 * TGAS);
+        assert_eq!(estimate_gas(&request(1_000, 0), None), MAX_GAS);
+        let mut root_signed = request(4, 2);
+        root_signed.signer_paths = vec![vec![[0; 32]; 2]; 4];
+        assert_eq!(estimate_gas(&root_signed, None), 39 * TGAS);
+        assert_eq!(bump_gas(100 * TGAS), 150 * TGAS);
+        assert_eq!(bump_gas(250 * TGAS), MAX_GAS);
+    }
+
+    #[test]
+    fn test_tx_id() {
+        let mut inst = vec![0u8; INST_TX_ID_OFFSET];
+        inst.extend_from_slice(&[7u8; 32]);
+        let mut req = request(0, 0);
+        req.inst = hex::encode(&inst);
+        assert_eq!(req.tx_id().unwrap(), [7u8; 32]);
+        req.inst = hex::encode(&inst[..100]);
+        assert!(req.tx_id().is_err());
+    }
+
+    #[test]
+    fn test_method() {
+        let mut req = request(0, 0);
+        req.inst = hex::encode([157u8, 1]);
+        assert_eq!(req.method(), "withdraw");
+        req.inst = hex::encode([NFT_WITHDRAW_METADATA, 1]);
+        assert_eq!(req.method(), "withdraw_nft");
+        req.inst = hex::encode([&[DOMAIN_INST_VERSION][..], &[0u8; 32], &[NFT_WITHDRAW_METADATA, 1]].concat());
+        assert_eq!(req.method(), "withdraw_nft");
+    }
+}
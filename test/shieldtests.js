@@ -57,7 +57,7 @@ const { connect } = nearAPI;
         contractId,
         {
             // name of contract you're connecting to
-            viewMethods: ["get_beacons", "get_tx_burn_used"], // view methods do not change state but usually return a value
+            viewMethods: ["get_beacons", "get_tx_burn_used", "is_burn_processed", "are_burns_processed", "get_storage_usage"], // view methods do not change state but usually return a value
             changeMethods: ["new", "deposit", "withdraw", "swap_beacon_committee", "submit_burn_proof"], // change methods modify state
             sender: account, // account object to initialize and sign transactions.
         }
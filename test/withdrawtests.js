@@ -91,7 +91,7 @@ const axios = require('axios');
             unshield_info: unshieldInfo,
         },
         "300000000000000",
-        "0"
+        "100000000000000000000000" // storage deposit, unused part is refunded
     );
 
 })();